@@ -1,12 +1,18 @@
 use std::{cell::RefCell, collections::{HashSet, HashMap}};
 
 use candid::{CandidType, Deserialize, Principal, candid_method};
-use ic_cdk::api::management_canister::http_request::{TransformArgs, HttpResponse, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext, TransformFunc, http_request};
+use ic_cdk::api::call::{call_with_payment128, CallResult};
+use ic_cdk::api::management_canister::http_request::{TransformArgs, HttpResponse, CanisterHttpRequestArgument, HttpHeader, HttpMethod, TransformContext, TransformFunc};
 use ic_cdk_macros::*;
-use jsonrpc_core::Call;
+use ic_web3::transforms::{rpc_transform, RpcContext};
+use ic_web3::transports::ic_http_client::http_request_required_cycles;
+use ic_web3::transports::ic_proxy::{Registered, RpcTarget};
+use ic_web3::transports::ResponseSizePolicy;
+use jsonrpc_core::Request;
 
 const MIN_CYCLES_REQUIRED: u128 = 10_000_000_000; // 10B cycles minimum for each call
 const SERVICE_FEE: u128 = 100_000_000; // 0.1B cycles for service fee
+const SUBNET_SIZE: u64 = 13; // nodes of the subnet the endpoint is deployed on
 
 #[derive(CandidType, Deserialize)]
 struct State {
@@ -15,20 +21,6 @@ struct State {
     registered: HashMap<Registered, String>,
 }
 
-#[derive(CandidType, Deserialize, Eq, PartialEq, Hash, Clone)]
-struct Registered {
-    chain_id: u64,
-    api_provider: String,
-}
-
-#[derive(CandidType, Deserialize, Clone)]
-enum RpcTarget {
-    #[serde(rename = "registered")]
-    Registered(Registered),
-    #[serde(rename = "url_with_api_key")]
-    UrlWithApiKey(String),
-}
-
 impl Default for State {
     fn default() -> Self {
         Self { 
@@ -56,9 +48,8 @@ fn init() {
 #[query(name = "transform")]
 #[candid_method(query, rename = "transform")]
 fn transform(response: TransformArgs) -> HttpResponse {
-    let res = response.response;
-    // remove header
-    HttpResponse { status: res.status, headers: Vec::default(), body: res.body }
+    // remove header and sanitise the body by json rpc method
+    rpc_transform(response)
 }
 
 // get state info
@@ -102,17 +93,11 @@ async fn json_rpc(payload: String, target: RpcTarget, max_response_bytes: Option
     if cycles_call < MIN_CYCLES_REQUIRED {
         return Err(format!("requires at least 10B cycles, get {} cycles", cycles_call));
     }
-    let request_body: Call = serde_json::from_str(payload.as_ref()).map_err(|e| format!("Fail to decode json body: {:?}", e))?;
-    let max_resp = max_response_bytes.unwrap_or(get_default_max_response_bytes_by_call(&request_body));
-    let cycles_estimated = calculate_required_cycles(payload.clone(), max_resp, target.clone());
-    if cycles_call < cycles_estimated {
-        return Err(format!("requires {} cycles, get {} cycles", cycles_estimated, cycles_call));
-    }
-    // charge cycles
-    let cycles_charged = ic_cdk::api::call::msg_cycles_accept128(cycles_estimated);
-    ic_cdk::println!("cycles charged: {}", cycles_charged);
-   
-    let url_with_key = match target {
+    // a single call or a batch
+    let request_body: Request = serde_json::from_str(payload.as_ref()).map_err(|e| format!("Fail to decode json body: {:?}", e))?;
+    let max_resp = max_response_bytes.unwrap_or(get_default_max_response_bytes_by_request(&request_body));
+
+    let url_with_key = match target.clone() {
         RpcTarget::Registered(registered) => {
             STATE.with(|s| {
                 s.borrow().registered.get(&registered).cloned().unwrap_or_default()
@@ -126,7 +111,17 @@ async fn json_rpc(payload: String, target: RpcTarget, max_response_bytes: Option
         return Err("url is empty".to_string())
     };
 
-    let call_res = json_rpc_call(&request_body, url_with_key, max_resp).await;
+    let request = build_http_request(&request_body, url_with_key, max_resp);
+    let outcall_cycles = http_request_required_cycles(&request, SUBNET_SIZE);
+    let cycles_estimated = calculate_required_cycles(payload.clone(), max_resp, target) + outcall_cycles;
+    if cycles_call < cycles_estimated {
+        return Err(format!("requires {} cycles, get {} cycles", cycles_estimated, cycles_call));
+    }
+    // charge cycles
+    let cycles_charged = ic_cdk::api::call::msg_cycles_accept128(cycles_estimated);
+    ic_cdk::println!("cycles charged: {}", cycles_charged);
+
+    let call_res = json_rpc_call(request, outcall_cycles).await;
     
     let res = call_res.map_err(|e| format!("{}", e))?;
     ic_cdk::println!("result: {}", res);
@@ -134,16 +129,14 @@ async fn json_rpc(payload: String, target: RpcTarget, max_response_bytes: Option
     Ok(format!("{}", res))
 }
 
-/// Call json rpc directly
-pub async fn json_rpc_call(request_body: &Call, url: String, max_response_bytes: u64) -> Result<String, String> {
+fn build_http_request(request_body: &Request, url: String, max_response_bytes: u64) -> CanisterHttpRequestArgument {
     let request_headers = vec![
             HttpHeader {
                 name: "Content-Type".to_string(),
                 value: "application/json".to_string(),
             },
         ];
-    // call http
-    let request = CanisterHttpRequestArgument {
+    CanisterHttpRequestArgument {
         url: url,
         max_response_bytes: Some(max_response_bytes),
         method: HttpMethod::POST,
@@ -154,11 +147,16 @@ pub async fn json_rpc_call(request_body: &Call, url: String, max_response_bytes:
                     principal: ic_cdk::api::id(),
                     method: "transform".to_string(),
                 }),
-            context: vec![],
+            context: RpcContext::from_request(request_body).encode(),
         }),
-    };
+    }
+}
 
-    match http_request(request).await {
+/// Call json rpc directly, attaching the given cycles to the outcall
+pub async fn json_rpc_call(request: CanisterHttpRequestArgument, cycles: u128) -> Result<String, String> {
+    let response: CallResult<(HttpResponse,)> =
+        call_with_payment128(Principal::management_canister(), "http_request", (request,), cycles).await;
+    match response {
         Ok((result, )) => {
             Ok(String::from_utf8_lossy(result.body.as_ref()).to_string())
         }
@@ -170,9 +168,8 @@ pub async fn json_rpc_call(request_body: &Call, url: String, max_response_bytes:
     }
 }
 
-fn get_default_max_response_bytes_by_call(rpc_call: &Call) -> u64 {
-    // TODO define the max response bytes by call method
-    return 500_000;
+fn get_default_max_response_bytes_by_request(request: &Request) -> u64 {
+    ResponseSizePolicy::default().for_request(request)
 }
 
 fn is_owner() -> Result<(), String> {
@@ -198,18 +195,15 @@ fn is_authorized() -> Result<(), String> {
     })
 }
 
-// calculate the estimated cycles required
+// calculate the estimated cycles required besides the http outcall, which is priced by
+// `http_request_required_cycles`
 // refer to https://internetcomputer.org/docs/current/developer-docs/gas-cost
 fn calculate_required_cycles(payload: String, max_response_bytes: u64, target: RpcTarget) -> u128 {
     let arg_raw = candid::utils::encode_args((payload, max_response_bytes, target)).expect("Failed to encode arguments.");
     // 1.2M is ingress message received
     // 2K per byte received in an ingress message
-    // 400M is HTTPS outcall request
-    // assuming ingress message size is almost the same size of http request size, 100K cycles per byte
     1_200_000u128 + 
         2_000u128 * arg_raw.len() as u128 + 
-        400_000_000u128 + 
-        100_000u128 * (arg_raw.len() as u128 + max_response_bytes as u128) +
         SERVICE_FEE
 }
 
@@ -0,0 +1,86 @@
+// Local stand-in for the EVM RPC canister, answering with canned values.
+// Deploy it with dfx and point `EvmRpc` at it to test canisters without providers.
+use candid::{candid_method, Nat};
+use ic_cdk_macros::*;
+use ic_web3::signing::keccak256;
+use ic_web3::transports::evm_rpc::{
+    GetTransactionCountArgs, MultiGetTransactionCountResult, MultiSendRawTransactionResult,
+    RequestCostResult, RequestResult, RpcConfig, RpcError, RpcService, RpcServices,
+    SendRawTransactionStatus, ValidationError,
+};
+use jsonrpc_core::{Call, Id, Output, Request, Success, Value};
+
+const REQUEST_COST: u64 = 1_000_000;
+const TRANSACTION_COUNT: u64 = 5;
+
+fn canned(method: &str) -> Value {
+    match method {
+        "eth_chainId" => "0x1".into(),
+        "eth_blockNumber" => "0x10".into(),
+        "eth_gasPrice" => "0x3b9aca00".into(),
+        "eth_getBalance" => "0xde0b6b3a7640000".into(),
+        _ => Value::Null,
+    }
+}
+
+#[query(name = "requestCost")]
+#[candid_method(query, rename = "requestCost")]
+fn request_cost(_service: RpcService, _json: String, _max_response_bytes: u64) -> RequestCostResult {
+    Ok(Nat::from(REQUEST_COST))
+}
+
+#[update(name = "request")]
+#[candid_method(update, rename = "request")]
+fn request(_service: RpcService, json: String, _max_response_bytes: u64) -> RequestResult {
+    ic_cdk::api::call::msg_cycles_accept128(REQUEST_COST as u128);
+    let invalid = |e: serde_json::Error| RpcError::ValidationError(ValidationError::Custom(e.to_string()));
+    let answer = |call: Call| match call {
+        Call::MethodCall(call) => Output::Success(Success {
+            jsonrpc: call.jsonrpc,
+            result: canned(&call.method),
+            id: call.id,
+        }),
+        _ => Output::Success(Success {
+            jsonrpc: None,
+            result: Value::Null,
+            id: Id::Null,
+        }),
+    };
+    let response = match serde_json::from_str::<Request>(&json).map_err(invalid)? {
+        Request::Single(call) => serde_json::to_string(&answer(call)),
+        Request::Batch(calls) => serde_json::to_string(&calls.into_iter().map(answer).collect::<Vec<_>>()),
+    };
+    response.map_err(invalid)
+}
+
+#[update(name = "eth_getTransactionCount")]
+#[candid_method(update, rename = "eth_getTransactionCount")]
+fn eth_get_transaction_count(
+    _services: RpcServices,
+    _config: Option<RpcConfig>,
+    _args: GetTransactionCountArgs,
+) -> MultiGetTransactionCountResult {
+    MultiGetTransactionCountResult::Consistent(Ok(Nat::from(TRANSACTION_COUNT)))
+}
+
+#[update(name = "eth_sendRawTransaction")]
+#[candid_method(update, rename = "eth_sendRawTransaction")]
+fn eth_send_raw_transaction(
+    _services: RpcServices,
+    _config: Option<RpcConfig>,
+    raw: String,
+) -> MultiSendRawTransactionResult {
+    let status = hex::decode(raw.trim_start_matches("0x"))
+        .map(|bytes| SendRawTransactionStatus::Ok(Some(format!("0x{}", hex::encode(keccak256(&bytes))))))
+        .map_err(|e| RpcError::ValidationError(ValidationError::InvalidHex(e.to_string())));
+    MultiSendRawTransactionResult::Consistent(status)
+}
+
+#[cfg(not(any(target_arch = "wasm32", test)))]
+fn main() {
+    candid::export_service!();
+    std::print!("{}", __export_service());
+}
+
+#[cfg(any(target_arch = "wasm32", test))]
+fn main() {}
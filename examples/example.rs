@@ -1,11 +1,13 @@
-use candid::candid_method;
+use candid::{candid_method, Principal};
 use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
 use ic_cdk_macros::{self, update, query};
 use std::str::FromStr;
 
-use ic_web3::transports::ICHttp;
+use ic_web3::transports::evm_rpc::{EthMainnetService, EvmRpc, RpcService, RpcServices};
+use ic_web3::transports::{ICHttp, Retry, RetryPolicy};
 use ic_web3::Web3;
-use ic_web3::ic::{get_eth_addr, KeyInfo};
+use ic_web3::signing::Signer;
+use ic_web3::ic::{get_eth_addr, IcSigner, KeyInfo};
 use ic_web3::{
     contract::{Contract, Options},
     ethabi::ethereum_types::{U64, U256},
@@ -24,18 +26,17 @@ type Result<T, E> = std::result::Result<T, E>;
 #[query(name = "transform")]
 #[candid_method(query, rename = "transform")]
 fn transform(response: TransformArgs) -> HttpResponse {
-    let mut t = response.response;
-    t.headers = vec![];
-    t 
+    // strips headers and canonicalises the JSON body, see `ic_web3::register_transform!`
+    ic_web3::transforms::rpc_transform(response)
 }
 
 #[update(name = "get_block")]
 #[candid_method(update, rename = "get_block")]
 async fn get_block(number: u64) -> Result<String, String> {
-    let w3 = match ICHttp::new(URL, None) {
-        Ok(v) => { Web3::new(v) },
-        Err(e) => { return Err(e.to_string()) },
-    };
+    let mut http = ICHttp::new(URL, None).map_err(|e| e.to_string())?;
+    // let the transform know which method it cleans up
+    http.set_transform_hook(ic_web3::transforms::rpc_transform_hook);
+    let w3 = Web3::new(http);
     let block_id = BlockId::from(U64::from(number));
     let block = w3.eth().block(block_id).await.map_err(|e| format!("get block error: {}", e))?;
     ic_cdk::println!("block: {:?}", block.clone().unwrap());
@@ -46,10 +47,9 @@ async fn get_block(number: u64) -> Result<String, String> {
 #[update(name = "get_eth_gas_price")]
 #[candid_method(update, rename = "get_eth_gas_price")]
 async fn get_eth_gas_price() -> Result<String, String> {
-    let w3 = match ICHttp::new(URL, None) {
-        Ok(v) => { Web3::new(v) },
-        Err(e) => { return Err(e.to_string()) },
-    };
+    let http = ICHttp::new(URL, None).map_err(|e| e.to_string())?;
+    // retry transient rejections and rate limits, waiting on IC timers
+    let w3 = Web3::new(Retry::new(http, RetryPolicy::default()));
     let gas_price = w3.eth().gas_price().await.map_err(|e| format!("get gas price failed: {}", e))?;
     ic_cdk::println!("gas price: {}", gas_price);
     Ok(format!("{}", gas_price))
@@ -92,11 +92,24 @@ async fn get_eth_balance(addr: String) -> Result<String, String> {
     Ok(format!("{}", balance))
 }
 
+// tx count through the EVM RPC canister, or the evm_rpc_stub example deployed locally
+#[update(name = "evm_rpc_tx_count")]
+#[candid_method(update, rename = "evm_rpc_tx_count")]
+async fn evm_rpc_tx_count(canister: Principal, addr: String) -> Result<String, String> {
+    let mut evm = EvmRpc::new(canister, RpcService::EthMainnet(EthMainnetService::PublicNode));
+    // ask the canister default providers and fail unless they agree
+    evm.set_services(RpcServices::EthMainnet(None), None);
+    let w3 = Web3::new(evm);
+    let addr = Address::from_str(&addr).map_err(|e| format!("invalid address: {}", e))?;
+    let count = w3.eth().transaction_count(addr, None).await.map_err(|e| format!("get tx count failed: {}", e))?;
+    Ok(format!("{}", count))
+}
+
 #[update(name = "batch_request")]
 #[candid_method(update, rename = "batch_request")]
 async fn batch_request() -> Result<String, String> {
     let http = ICHttp::new(URL, None).map_err(|e| format!("init ICHttp failed: {}", e))?;
-    let w3 = Web3::new(ic_web3::transports::Batch::new(http));
+    let w3 = Web3::new(ic_web3::transports::Batch::new(Retry::new(http, RetryPolicy::default())));
 
     let block_number = w3.eth().block_number();
     let gas_price = w3.eth().gas_price();
@@ -126,14 +139,15 @@ async fn send_eth(to: String, value: u64, nonce: Option<u64>) -> Result<String,
     let key_info = KeyInfo{ derivation_path: derivation_path, key_name: KEY_NAME.to_string(), ecdsa_sign_cycles: None };
 
     // get canister eth address
-    let from_addr = get_eth_addr(None, None, KEY_NAME.to_string())
+    let signer = IcSigner::new(key_info)
         .await
         .map_err(|e| format!("get canister eth addr failed: {}", e))?;
+    let from_addr = signer.address();
     // get canister the address tx count
-    let w3 = match ICHttp::new(URL, None) {
-        Ok(v) => { Web3::new(v) },
-        Err(e) => { return Err(e.to_string()) },
-    };
+    let mut http = ICHttp::new(URL, None).map_err(|e| e.to_string())?;
+    // every replica submits the tx, make them all agree on its hash
+    http.set_raw_transaction_mode(true);
+    let w3 = Web3::new(http);
     let tx_count: U256 = if let Some(count) = nonce {
         count.into() 
     } else {
@@ -152,21 +166,21 @@ async fn send_eth(to: String, value: u64, nonce: Option<u64>) -> Result<String,
         nonce: Some(tx_count), // remember to fetch nonce first
         value: U256::from(value),
         gas_price: Some(U256::from(100_000_000_000u64)), // 100 gwei
-        gas: U256::from(21000),
+        gas: Some(U256::from(21000)),
+        chain_id: Some(CHAIN_ID),
         ..Default::default()
     };
     // sign the transaction and get serialized transaction + signature
     let signed_tx = w3.accounts()
-        .sign_transaction(tx, hex::encode(from_addr), key_info, CHAIN_ID)
+        .sign_transaction(tx, &signer)
         .await
         .map_err(|e| format!("sign tx error: {}", e))?;
-    match w3.eth().send_raw_transaction(signed_tx.raw_transaction).await {
-        Ok(txhash) => { 
-            ic_cdk::println!("txhash: {}", hex::encode(txhash.0));
-            Ok(format!("{}", hex::encode(txhash.0)))
-        },
-        Err(_e) => { Ok(hex::encode(signed_tx.message_hash)) },
-    }
+    let txhash = w3.eth()
+        .send_raw_transaction(signed_tx.raw_transaction)
+        .await
+        .map_err(|e| format!("send tx error: {}", e))?;
+    ic_cdk::println!("txhash: {}", hex::encode(txhash.0));
+    Ok(hex::encode(txhash.0))
 }
 
 // query a contract, token balance
@@ -204,9 +218,10 @@ async fn send_token(token_addr: String, addr: String, value: u64, nonce: Option<
     let key_info = KeyInfo{ derivation_path: derivation_path, key_name: KEY_NAME.to_string(), ecdsa_sign_cycles: None };
 
     // get canister eth address
-    let from_addr = get_eth_addr(None, None, KEY_NAME.to_string())
+    let signer = IcSigner::new(key_info)
         .await
         .map_err(|e| format!("get canister eth addr failed: {}", e))?;
+    let from_addr = signer.address();
     let w3 = match ICHttp::new(URL, None) {
         Ok(v) => { Web3::new(v) },
         Err(e) => { return Err(e.to_string()) },
@@ -218,9 +233,6 @@ async fn send_token(token_addr: String, addr: String, value: u64, nonce: Option<
         TOKEN_ABI
     ).map_err(|e| format!("init contract failed: {}", e))?;
 
-    let canister_addr = get_eth_addr(None, None, KEY_NAME.to_string())
-        .await
-        .map_err(|e| format!("get canister eth addr failed: {}", e))?;
     // add nonce to options
     let tx_count: U256 = if let Some(count) = nonce {
         count.into() 
@@ -245,7 +257,7 @@ async fn send_token(token_addr: String, addr: String, value: u64, nonce: Option<
     });
     let to_addr = Address::from_str(&addr).unwrap();
     let txhash = contract
-        .signed_call("transfer", (to_addr, value,), options, hex::encode(canister_addr), key_info, CHAIN_ID)
+        .signed_call("transfer", (to_addr, value,), options, &signer, Some(CHAIN_ID))
         .await
         .map_err(|e| format!("token transfer failed: {}", e))?;
 
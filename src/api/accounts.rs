@@ -1,7 +1,6 @@
 //! Partial implementation of the `Accounts` namespace.
 
 use crate::{api::Namespace, signing, types::H256, Transport};
-use crate::ic::{KeyInfo, ic_raw_sign, recover_address};
 
 /// `Accounts` namespace
 #[derive(Debug, Clone)]
@@ -42,11 +41,17 @@ mod accounts_signing {
     use crate::{
         api::Web3,
         error,
-        signing::Signature,
+        signing::{Signature, Signer, SigningError},
+        transports::Batch,
         types::{
-            AccessList, Address, Bytes, Recovery, RecoveryMessage, SignedData, SignedTransaction,
-            TransactionParameters, U256, U64,
+            AccessList, Address, BlockNumber, Bytes, CallRequest, FeeHistory, Recovery, RecoveryMessage, SignedData,
+            SignedTransaction, TransactionParameters, U256, U64,
         },
+        BatchTransport,
+    };
+    use futures::{
+        future::{self, Either, OptionFuture},
+        Future, FutureExt, TryFutureExt,
     };
     use rlp::RlpStream;
     // use std::convert::TryInto;
@@ -55,173 +60,208 @@ mod accounts_signing {
     const ACCESSLISTS_TX_ID: u64 = 1;
     const EIP1559_TX_ID: u64 = 2;
 
+    /// Number of recent blocks EIP-1559 fees are derived from.
+    const FEE_HISTORY_BLOCKS: u64 = 10;
+    /// Percentile of the priority fees paid in recent blocks offered as priority fee.
+    const PRIORITY_FEE_PERCENTILE: f64 = 50.0;
+    /// Margin added to estimated gas, in percent, as the state may change before inclusion.
+    const GAS_MARGIN_PERCENT: u64 = 20;
+
+    /// Resolves to `value` if given, otherwise sends `call` right away and resolves to its answer.
+    fn given_or<V, F>(value: Option<V>, call: impl FnOnce() -> F) -> impl Future<Output = error::Result<V>>
+    where
+        F: Future<Output = error::Result<V>>,
+    {
+        match value {
+            Some(value) => Either::Left(future::ok(value)),
+            None => Either::Right(call()),
+        }
+    }
+
+    /// Max fee and priority fee per gas, filling those not given from the fee history.
+    ///
+    /// The priority fee is the median of the rewards paid at [PRIORITY_FEE_PERCENTILE], and the
+    /// max fee twice the base fee of the next block plus the priority fee, which stays valid for a
+    /// few full blocks.
+    fn eip1559_fees(
+        history: Option<&FeeHistory>,
+        max_fee_per_gas: Option<U256>,
+        max_priority_fee_per_gas: Option<U256>,
+    ) -> (U256, U256) {
+        let base_fee = history
+            .and_then(|history| history.base_fee_per_gas.last().copied())
+            .unwrap_or_default();
+        let mut rewards = history
+            .and_then(|history| history.reward.as_ref())
+            .into_iter()
+            .flatten()
+            .filter_map(|rewards| rewards.first().copied())
+            .collect::<Vec<_>>();
+        rewards.sort();
+        let suggested = rewards.get(rewards.len() / 2).copied().unwrap_or_default();
+
+        let max_priority_fee_per_gas = max_priority_fee_per_gas.unwrap_or(suggested);
+        let max_fee_per_gas = max_fee_per_gas
+            .unwrap_or_else(|| base_fee.saturating_mul(2.into()).saturating_add(max_priority_fee_per_gas));
+        (max_fee_per_gas, max_priority_fee_per_gas.min(max_fee_per_gas))
+    }
+
     impl<T: Transport> Accounts<T> {
         /// Gets the parent `web3` namespace
         fn web3(&self) -> Web3<T> {
             Web3::new(self.transport.clone())
         }
 
-        // Signs an Ethereum transaction with a given private key.
-        //
-        // Transaction signing can perform RPC requests in order to fill missing
-        // parameters required for signing `nonce`, `gas_price` and `chain_id`. Note
-        // that if all transaction parameters were provided, this future will resolve
-        // immediately.
-        // pub async fn sign_transaction<K: signing::Key>(
-        //     &self,
-        //     tx: TransactionParameters,
-        //     key: K,
-        // ) -> error::Result<SignedTransaction> {
-        //     macro_rules! maybe {
-        //         ($o: expr, $f: expr) => {
-        //             async {
-        //                 match $o {
-        //                     Some(value) => Ok(value),
-        //                     None => $f.await,
-        //                 }
-        //             }
-        //         };
-        //     }
-        //     let from = key.address();
-
-        //     let gas_price = match tx.transaction_type {
-        //         Some(tx_type) if tx_type == U64::from(EIP1559_TX_ID) && tx.max_fee_per_gas.is_some() => {
-        //             tx.max_fee_per_gas
-        //         }
-        //         _ => tx.gas_price,
-        //     };
-
-        //     let (nonce, gas_price, chain_id) = futures::future::try_join3(
-        //         maybe!(tx.nonce, self.web3().eth().transaction_count(from, None)),
-        //         maybe!(gas_price, self.web3().eth().gas_price()),
-        //         maybe!(tx.chain_id.map(U256::from), self.web3().eth().chain_id()),
-        //     )
-        //     .await?;
-        //     let chain_id = chain_id.as_u64();
-
-        //     let max_priority_fee_per_gas = match tx.transaction_type {
-        //         Some(tx_type) if tx_type == U64::from(EIP1559_TX_ID) => {
-        //             tx.max_priority_fee_per_gas.unwrap_or(gas_price)
-        //         }
-        //         _ => gas_price,
-        //     };
-
-        //     let tx = Transaction {
-        //         to: tx.to,
-        //         nonce,
-        //         gas: tx.gas,
-        //         gas_price,
-        //         value: tx.value,
-        //         data: tx.data.0,
-        //         transaction_type: tx.transaction_type,
-        //         access_list: tx.access_list.unwrap_or_default(),
-        //         max_priority_fee_per_gas,
-        //     };
-
-        //     let signed = tx.sign(key, chain_id);
-        //     Ok(signed)
-        // }
-        pub async fn sign_transaction(
+        /// Signs an Ethereum transaction with any [Signer], e.g. the canister's threshold ECDSA key.
+        ///
+        /// Parameters missing from `tx` are looked up first: the pending nonce of the signer, the
+        /// gas price or EIP-1559 fees derived from the recent fee history, the chain id, and the
+        /// gas, estimated with a safety margin. If all parameters are given, no request is made.
+        ///
+        /// Each lookup is a request of its own, up to four outcalls. With a [BatchTransport], use
+        /// [Accounts::sign_transaction_batched] to send them in one.
+        pub async fn sign_transaction<S: Signer>(
             &self,
             tx: TransactionParameters,
-            from: String,
-            key_info: KeyInfo,
-            chain_id: u64,
+            signer: &S,
         ) -> error::Result<SignedTransaction> {
+            let (tx, chain_id) = self.fill(tx, signer.address()).await?;
+            tx.sign(signer, chain_id).await
+        }
 
-            let gas_price = match tx.transaction_type {
-                Some(tx_type) if tx_type == U64::from(EIP1559_TX_ID) && tx.max_fee_per_gas.is_some() => {
-                    tx.max_fee_per_gas.unwrap()
-                }
-                _ => tx.gas_price.unwrap(),
-            };
-
-            let max_priority_fee_per_gas = match tx.transaction_type {
-                Some(tx_type) if tx_type == U64::from(EIP1559_TX_ID) => {
-                    tx.max_priority_fee_per_gas.unwrap_or(gas_price)
-                }
-                _ => gas_price,
+        /// Sends the lookups of the parameters `tx` lacks, resolving to the transaction to sign
+        /// and its chain id once all are answered.
+        fn fill(
+            &self,
+            tx: TransactionParameters,
+            from: Address,
+        ) -> impl Future<Output = error::Result<(Transaction, u64)>> {
+            let eth = self.web3().eth();
+
+            let nonce = given_or(tx.nonce, || eth.transaction_count(from, Some(BlockNumber::Pending)));
+            let fees = if tx.transaction_type == Some(U64::from(EIP1559_TX_ID)) {
+                let max_fee_per_gas = tx.max_fee_per_gas.or(tx.gas_price);
+                let max_priority_fee_per_gas = tx.max_priority_fee_per_gas;
+                let history = (max_fee_per_gas.is_none() || max_priority_fee_per_gas.is_none()).then(|| {
+                    let percentiles = Some(vec![PRIORITY_FEE_PERCENTILE]);
+                    eth.fee_history(FEE_HISTORY_BLOCKS.into(), BlockNumber::Latest, percentiles)
+                });
+                Either::Left(OptionFuture::from(history).map(move |history| {
+                    let history = history.transpose()?;
+                    Ok(eip1559_fees(history.as_ref(), max_fee_per_gas, max_priority_fee_per_gas))
+                }))
+            } else {
+                Either::Right(given_or(tx.gas_price, || eth.gas_price()).map_ok(|gas_price| (gas_price, gas_price)))
             };
-
-            let tx = Transaction {
+            let estimate = CallRequest {
+                from: Some(from),
                 to: tx.to,
-                nonce: tx.nonce.unwrap(),
-                gas: tx.gas,
-                gas_price,
-                value: tx.value,
-                data: tx.data.0,
+                value: Some(tx.value),
+                data: Some(tx.data.clone()),
                 transaction_type: tx.transaction_type,
-                access_list: tx.access_list.unwrap_or_default(),
-                max_priority_fee_per_gas,
+                access_list: tx.access_list.clone(),
+                ..Default::default()
             };
+            let gas = given_or(tx.gas, || {
+                eth.estimate_gas(estimate, None)
+                    .map_ok(|gas| gas.saturating_add(gas.saturating_mul(GAS_MARGIN_PERCENT.into()) / 100))
+            });
+            let chain_id = given_or(tx.chain_id.map(U256::from), || eth.chain_id());
+
+            async move {
+                let (nonce, (gas_price, max_priority_fee_per_gas), gas, chain_id) =
+                    future::try_join4(nonce, fees, gas, chain_id).await?;
+                if chain_id > U256::from(u64::MAX) {
+                    return Err(error::Error::InvalidResponse(format!("chain id {} is too large", chain_id)));
+                }
+                let tx = Transaction {
+                    to: tx.to,
+                    nonce,
+                    gas,
+                    gas_price,
+                    value: tx.value,
+                    data: tx.data.0,
+                    transaction_type: tx.transaction_type,
+                    access_list: tx.access_list.unwrap_or_default(),
+                    max_priority_fee_per_gas,
+                };
+                Ok((tx, chain_id.as_u64()))
+            }
+        }
 
-            let signed = tx.sign(from, key_info, chain_id).await;
-            Ok(signed)
+        /// Sign arbitrary string data with any [Signer], e.g. the canister's threshold ECDSA key.
+        ///
+        /// The data is UTF-8 encoded and enveloped the same way as with
+        /// `hash_message`, as by `personal_sign`. The returned signed data's signature is in 'Electrum'
+        /// notation, that is the recovery value `v` is either `27` or `28` (as
+        /// opposed to the standard notation where `v` is either `0` or `1`), which
+        /// Solidity's `ecrecover` expects.
+        pub async fn sign<M, S>(&self, message: M, signer: &S) -> error::Result<SignedData>
+        where
+            M: AsRef<[u8]>,
+            S: Signer,
+        {
+            let message = message.as_ref();
+            let message_hash = self.hash_message(message);
+
+            let signature = signer.sign_hash(message_hash.0).await?;
+            let (signature, recovery_id) = signing::recoverable(&message_hash.0, &signature, signer.address())?;
+            let v = 27 + recovery_id as u8;
+
+            let signature_bytes = Bytes({
+                let mut bytes = Vec::with_capacity(65);
+                bytes.extend_from_slice(&signature);
+                bytes.push(v);
+                bytes
+            });
+
+            Ok(SignedData {
+                message: message.to_owned(),
+                message_hash,
+                v,
+                r: H256::from_slice(&signature[..32]),
+                s: H256::from_slice(&signature[32..]),
+                signature: signature_bytes,
+            })
         }
 
-        // Sign arbitrary string data.
-        //
-        // The data is UTF-8 encoded and enveloped the same way as with
-        // `hash_message`. The returned signed data's signature is in 'Electrum'
-        // notation, that is the recovery value `v` is either `27` or `28` (as
-        // opposed to the standard notation where `v` is either `0` or `1`). This
-        // is important to consider when using this signature with other crates.
-        // pub fn sign<S>(&self, message: S, key: impl signing::Key) -> SignedData
-        // where
-        //     S: AsRef<[u8]>,
-        // {
-        //     let message = message.as_ref();
-        //     let message_hash = self.hash_message(message);
-
-        //     let signature = key
-        //         .sign(message_hash.as_bytes(), None)
-        //         .expect("hash is non-zero 32-bytes; qed");
-        //     let v = signature
-        //         .v
-        //         .try_into()
-        //         .expect("signature recovery in electrum notation always fits in a u8");
-
-        //     let signature_bytes = Bytes({
-        //         let mut bytes = Vec::with_capacity(65);
-        //         bytes.extend_from_slice(signature.r.as_bytes());
-        //         bytes.extend_from_slice(signature.s.as_bytes());
-        //         bytes.push(v);
-        //         bytes
-        //     });
-
-        //     // We perform this allocation only after all previous fallible actions have completed successfully.
-        //     let message = message.to_owned();
-
-        //     SignedData {
-        //         message,
-        //         message_hash,
-        //         v,
-        //         r: signature.r,
-        //         s: signature.s,
-        //         signature: signature_bytes,
-        //     }
-        // }
+        /// Recovers the Ethereum address which was used to sign the given data.
+        ///
+        /// Recovery signature data uses 'Electrum' notation, this means the `v`
+        /// value is expected to be either `27` or `28`.
+        pub fn recover<R>(&self, recovery: R) -> error::Result<Address>
+        where
+            R: Into<Recovery>,
+        {
+            let recovery = recovery.into();
+            let message_hash = match recovery.message {
+                RecoveryMessage::Data(ref message) => self.hash_message(message),
+                RecoveryMessage::Hash(hash) => hash,
+            };
+            let (signature, recovery_id) = recovery
+                .as_signature()
+                .ok_or(error::Error::Recovery(signing::RecoveryError::InvalidSignature))?;
+            let address = signing::recover(message_hash.as_bytes(), &signature, recovery_id)?;
+            Ok(address)
+        }
+    }
 
-        // Recovers the Ethereum address which was used to sign the given data.
-        //
-        // Recovery signature data uses 'Electrum' notation, this means the `v`
-        // value is expected to be either `27` or `28`.
-        // pub fn recover<R>(&self, recovery: R) -> error::Result<Address>
-        // where
-        //     R: Into<Recovery>,
-        // {
-        //     let recovery = recovery.into();
-        //     let message_hash = match recovery.message {
-        //         RecoveryMessage::Data(ref message) => self.hash_message(message),
-        //         RecoveryMessage::Hash(hash) => hash,
-        //     };
-        //     let (signature, recovery_id) = recovery
-        //         .as_signature()
-        //         .ok_or(error::Error::Recovery(signing::RecoveryError::InvalidSignature))?;
-        //     let address = signing::recover(message_hash.as_bytes(), &signature, recovery_id)?;
-        //     Ok(address)
-        // }
+    impl<T: BatchTransport> Accounts<T> {
+        /// Signs a transaction like [Accounts::sign_transaction], sending the lookups of missing
+        /// parameters as a single batch, i.e. in one outcall.
+        pub async fn sign_transaction_batched<S: Signer>(
+            &self,
+            tx: TransactionParameters,
+            signer: &S,
+        ) -> error::Result<SignedTransaction> {
+            let batch = Batch::new(self.transport.clone());
+            let filled = Accounts::new(batch.clone()).fill(tx, signer.address());
+            batch.submit_batch().await?;
+            let (tx, chain_id) = filled.await?;
+            tx.sign(signer, chain_id).await
+        }
     }
+
     /// A transaction used for RLP encoding, hashing and signing.
     #[derive(Debug)]
     pub struct Transaction {
@@ -334,8 +374,8 @@ mod accounts_signing {
             }
         }
 
-        fn encode(&self, chain_id: u64, signature: Option<&Signature>) -> Vec<u8> {
-            match self.transaction_type.map(|t| t.as_u64()) {
+        fn encode(&self, chain_id: u64, signature: Option<&Signature>) -> Result<Vec<u8>, SigningError> {
+            Ok(match self.transaction_type.map(|t| t.as_u64()) {
                 Some(LEGACY_TX_ID) | None => {
                     let stream = self.encode_legacy(chain_id, signature);
                     stream.out().to_vec()
@@ -353,10 +393,8 @@ mod accounts_signing {
                     [&[tx_id], stream.as_raw()].concat()
                 }
 
-                _ => {
-                    panic!("Unsupported transaction type");
-                }
-            }
+                Some(tx_type) => return Err(SigningError::UnsupportedTransactionType(tx_type)),
+            })
         }
 
         /// Sign and return a raw signed transaction.
@@ -387,47 +425,40 @@ mod accounts_signing {
         //     }
         // }
 
-        pub async fn sign(self, from: String, key_info: KeyInfo, chain_id: u64) -> SignedTransaction {
+        /// Sign with `signer` and return a raw signed transaction.
+        ///
+        /// The signature is normalised to low-s form (EIP-2), and `v` derived from the recovery id
+        /// matching the signer's address.
+        pub async fn sign<S: Signer>(self, signer: &S, chain_id: u64) -> error::Result<SignedTransaction> {
             let adjust_v_value = matches!(self.transaction_type.map(|t| t.as_u64()), Some(LEGACY_TX_ID) | None);
 
-            let encoded = self.encode(chain_id, None);
+            let encoded = self.encode(chain_id, None)?;
 
             let hash = signing::keccak256(encoded.as_ref());
 
-            let res = match ic_raw_sign(hash.to_vec(), key_info).await {
-                Ok(v) => { v },
-                Err(e) => { panic!("{}", e); },
-            };
+            let signature = signer.sign_hash(hash).await?;
+            let (signature, recovery_id) = signing::recoverable(&hash, &signature, signer.address())?;
 
-            let v = if recover_address(hash.clone().to_vec(), res.clone(), 0) == from {
-                if adjust_v_value {
-                    2 * chain_id + 35 + 0
-                } else { 0 }
+            let v = if adjust_v_value {
+                2 * chain_id + 35 + recovery_id as u64
             } else {
-                if adjust_v_value {
-                    2 * chain_id + 35 + 1
-                } else { 1 }
+                recovery_id as u64
             };
-    
-            let r_arr = H256::from_slice(&res[0..32]);
-            let s_arr = H256::from_slice(&res[32..64]);
-            let sig = Signature {
-                v: v.clone(),
-                r: r_arr.clone().into(),
-                s: s_arr.clone().into()
-            };
-        
-            let signed = self.encode(chain_id, Some(&sig));
+            let r_arr = H256::from_slice(&signature[0..32]);
+            let s_arr = H256::from_slice(&signature[32..64]);
+            let sig = Signature { v, r: r_arr, s: s_arr };
+
+            let signed = self.encode(chain_id, Some(&sig))?;
             let transaction_hash = signing::keccak256(signed.as_ref()).into();
-        
-            SignedTransaction {
+
+            Ok(SignedTransaction {
                 message_hash: hash.into(),
                 v,
                 r: r_arr.into(),
                 s: s_arr.into(),
                 raw_transaction: signed.into(),
                 transaction_hash,
-            }
+            })
         }
     }
 }
@@ -436,9 +467,9 @@ mod accounts_signing {
 mod tests {
     use super::*;
     use crate::{
-        signing::{SecretKey, SecretKeyRef},
+        signing::{LocalSigner, Signer, SigningError},
         transports::test::TestTransport,
-        types::{Address, Recovery, SignedTransaction, TransactionParameters, U256},
+        types::{Address, CallRequest, Recovery, SignedTransaction, TransactionParameters, U256},
     };
     use accounts_signing::*;
     use hex_literal::hex;
@@ -452,17 +483,14 @@ mod tests {
         let tx = TransactionParameters {
             to: Some(hex!("F0109fC8DF283027b6285cc889F5aA624EaC1F55").into()),
             value: 1_000_000_000.into(),
-            gas: 2_000_000.into(),
+            gas: Some(2_000_000.into()),
             ..Default::default()
         };
-        let key = SecretKey::from_slice(&hex!(
-            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
-        ))
-        .unwrap();
+        let key = LocalSigner::new(&hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")).unwrap();
         let nonce = U256::zero();
         let gas_price = U256::from(21_000_000_000u128);
         let chain_id = "0x1";
-        let from: Address = signing::secret_key_address(&key);
+        let from: Address = key.address();
 
         let mut transport = TestTransport::default();
         transport.add_response(json!(nonce));
@@ -476,7 +504,7 @@ mod tests {
 
         transport.assert_request(
             "eth_getTransactionCount",
-            &[json!(from).to_string(), json!("latest").to_string()],
+            &[json!(from).to_string(), json!("pending").to_string()],
         );
         transport.assert_request("eth_gasPrice", &[]);
         transport.assert_request("eth_chainId", &[]);
@@ -496,15 +524,13 @@ mod tests {
 
     #[test]
     fn accounts_sign_transaction_with_all_parameters() {
-        let key = SecretKey::from_slice(&hex!(
-            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
-        ))
-        .unwrap();
+        let key = LocalSigner::new(&hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")).unwrap();
 
         let accounts = Accounts::new(TestTransport::default());
         futures::executor::block_on(accounts.sign_transaction(
             TransactionParameters {
                 nonce: Some(0.into()),
+                gas: Some(21_000.into()),
                 gas_price: Some(1.into()),
                 chain_id: Some(42),
                 ..Default::default()
@@ -517,6 +543,64 @@ mod tests {
         accounts.transport().assert_no_more_requests();
     }
 
+    #[test]
+    fn accounts_sign_transaction_should_fill_eip1559_fees_and_gas() {
+        let key = LocalSigner::new(&hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")).unwrap();
+        let to: Address = hex!("F0109fC8DF283027b6285cc889F5aA624EaC1F55").into();
+        let tx = TransactionParameters {
+            to: Some(to),
+            value: 1_000_000_000.into(),
+            chain_id: Some(1),
+            transaction_type: Some(2.into()),
+            ..Default::default()
+        };
+
+        let mut transport = TestTransport::default();
+        transport.add_response(json!("0x5"));
+        transport.add_response(json!({
+            "oldestBlock": "0x1",
+            "baseFeePerGas": ["0x64", "0xc8"],
+            "gasUsedRatio": [0.5],
+            "reward": [["0x3"]],
+        }));
+        transport.add_response(json!("0x5208"));
+
+        let signed = futures::executor::block_on(Accounts::new(&transport).sign_transaction(tx, &key));
+
+        transport.assert_request(
+            "eth_getTransactionCount",
+            &[json!(key.address()).to_string(), json!("pending").to_string()],
+        );
+        transport.assert_request(
+            "eth_feeHistory",
+            &[json!("0xa").to_string(), json!("latest").to_string(), json!([50.0]).to_string()],
+        );
+        let estimate = CallRequest {
+            from: Some(key.address()),
+            to: Some(to),
+            value: Some(1_000_000_000.into()),
+            data: Some(Default::default()),
+            transaction_type: Some(2.into()),
+            ..Default::default()
+        };
+        transport.assert_request("eth_estimateGas", &[json!(estimate).to_string()]);
+        transport.assert_no_more_requests();
+
+        // twice the next base fee plus the median reward, and 20% over the estimated gas
+        let expected = Transaction {
+            nonce: 5.into(),
+            gas: 25_200.into(),
+            gas_price: 403.into(),
+            to: Some(to),
+            value: 1_000_000_000.into(),
+            data: Vec::new(),
+            transaction_type: Some(2.into()),
+            access_list: vec![],
+            max_priority_fee_per_gas: 3.into(),
+        };
+        assert_eq!(signed, futures::executor::block_on(expected.sign(&key, 1)));
+    }
+
     #[test]
     fn accounts_hash_message() {
         // test vector taken from:
@@ -541,11 +625,8 @@ mod tests {
 
         let accounts = Accounts::new(TestTransport::default());
 
-        let key = SecretKey::from_slice(&hex!(
-            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
-        ))
-        .unwrap();
-        let signed = accounts.sign("Some data", SecretKeyRef::new(&key));
+        let key = LocalSigner::new(&hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")).unwrap();
+        let signed = futures::executor::block_on(accounts.sign("Some data", &key)).unwrap();
 
         assert_eq!(
             signed.message_hash,
@@ -583,21 +664,19 @@ mod tests {
 
     #[test]
     fn accounts_recover_signed() {
-        let key = SecretKey::from_slice(&hex!(
-            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
-        ))
-        .unwrap();
-        let address: Address = signing::secret_key_address(&key);
+        let key = LocalSigner::new(&hex!("000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f")).unwrap();
+        let address: Address = key.address();
 
         let accounts = Accounts::new(TestTransport::default());
 
-        let signed = accounts.sign("rust-web3 rocks!", &key);
+        let signed = futures::executor::block_on(accounts.sign("rust-web3 rocks!", &key)).unwrap();
         let recovered = accounts.recover(&signed).unwrap();
         assert_eq!(recovered, address);
 
         let signed = futures::executor::block_on(accounts.sign_transaction(
             TransactionParameters {
                 nonce: Some(0.into()),
+                gas: Some(21_000.into()),
                 gas_price: Some(1.into()),
                 chain_id: Some(42),
                 ..Default::default()
@@ -628,13 +707,9 @@ mod tests {
             access_list: vec![],
             max_priority_fee_per_gas: 0.into(),
         };
-        let skey = SecretKey::from_slice(&hex!(
-            "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318"
-        ))
-        .unwrap();
-        let key = SecretKeyRef::new(&skey);
+        let key = LocalSigner::new(&hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")).unwrap();
 
-        let signed = tx.sign(key, 1);
+        let signed = futures::executor::block_on(tx.sign(&key, 1)).unwrap();
 
         let expected = SignedTransaction {
             message_hash: hex!("6893a6ee8df79b0f5d64a180cd1ef35d030f3e296a5361cf04d02ce720d32ec5").into(),
@@ -647,4 +722,22 @@ mod tests {
 
         assert_eq!(signed, expected);
     }
+    #[test]
+    fn sign_transaction_should_refuse_unsupported_types() {
+        let tx = Transaction {
+            nonce: 0.into(),
+            gas: 2_000_000.into(),
+            gas_price: 234_567_897_654_321u64.into(),
+            to: None,
+            value: 0.into(),
+            data: Vec::new(),
+            transaction_type: Some(3.into()),
+            access_list: vec![],
+            max_priority_fee_per_gas: 0.into(),
+        };
+        let key = LocalSigner::new(&hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318")).unwrap();
+
+        let signed = futures::executor::block_on(tx.sign(&key, 1));
+        assert_eq!(signed, Err(SigningError::UnsupportedTransactionType(3).into()));
+    }
 }
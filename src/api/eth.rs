@@ -2,7 +2,9 @@
 
 use crate::{
     api::Namespace,
+    error,
     helpers::{self, CallFuture},
+    rpc,
     types::{
         Address, Block, BlockHeader, BlockId, BlockNumber, Bytes, CallRequest, FeeHistory, Filter, Index, Log, Proof,
         SyncState, Transaction, TransactionId, TransactionReceipt, TransactionRequest, Work, H256, H520, H64, U256,
@@ -121,6 +123,66 @@ impl<T: Transport> Eth<T> {
         CallFuture::new(self.transport.execute("eth_getLogs", vec![filter]))
     }
 
+    /// Get all logs matching a given filter, querying at most `window` blocks at a time.
+    ///
+    /// The window is halved whenever the provider refuses a range as too large, and grows back
+    /// after successful queries. Logs come in block order. Filters by block hash are sent as is.
+    pub async fn logs_paginated(&self, filter: Filter, window: u64) -> error::Result<Vec<Log>> {
+        let mut filter = helpers::serialize(&filter);
+        if filter.get("blockHash").is_some() {
+            return helpers::decode(self.transport.execute("eth_getLogs", vec![filter]).await?);
+        }
+        let mut latest = None;
+        let mut start = self.filter_block(filter.get("fromBlock"), &mut latest).await?;
+        let to = self.filter_block(filter.get("toBlock"), &mut latest).await?;
+
+        let max_window = window.max(1);
+        let mut window = max_window;
+        let mut logs = vec![];
+        while start <= to {
+            let end = to.min(start.saturating_add(window - 1));
+            filter["fromBlock"] = helpers::serialize(&U64::from(start));
+            filter["toBlock"] = helpers::serialize(&U64::from(end));
+            let page = self.transport.execute("eth_getLogs", vec![filter.clone()]).await;
+            match page.and_then(helpers::decode::<Vec<Log>>) {
+                Ok(page) => {
+                    logs.extend(page);
+                    start = end + 1;
+                    window = window.saturating_mul(2).min(max_window);
+                }
+                Err(err) if err.is_too_large() && end > start => window = (end - start + 1) / 2,
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(logs)
+    }
+
+    /// Number of a block of a filter, fetching the latest block number at most once.
+    ///
+    /// Other tags, such as `finalized`, `safe` or `pending`, are resolved to the number of the block they name.
+    async fn filter_block(&self, block: Option<&rpc::Value>, latest: &mut Option<u64>) -> error::Result<u64> {
+        match block.and_then(rpc::Value::as_str) {
+            Some("earliest") => Ok(0),
+            Some(number) if number.starts_with("0x") => Ok(helpers::decode::<U64>(number.into())?.as_u64()),
+            None | Some("latest") => {
+                if let Some(latest) = *latest {
+                    return Ok(latest);
+                }
+                let number = self.block_number().await?.as_u64();
+                *latest = Some(number);
+                Ok(number)
+            }
+            Some(tag) => {
+                let params = vec![tag.into(), helpers::serialize(&false)];
+                let block = self.transport.execute("eth_getBlockByNumber", params).await?;
+                match block.get("number").cloned() {
+                    Some(number) if !number.is_null() => Ok(helpers::decode::<U64>(number)?.as_u64()),
+                    _ => Err(error::Error::InvalidResponse(format!("no {} block", tag))),
+                }
+            }
+        }
+    }
+
     /// Get block details with transaction hashes.
     pub fn block(&self, block: BlockId) -> CallFuture<Option<Block<H256>>, T::Out> {
         let include_txs = helpers::serialize(&false);
@@ -390,7 +452,7 @@ mod tests {
         types::{
             Address, Block, BlockHeader, BlockId, BlockNumber, CallRequest, FeeHistory, FilterBuilder, Log, Proof,
             SyncInfo, SyncState, Transaction, TransactionId, TransactionReceipt, TransactionRequest, Work, H256, H520,
-            H64, U256,
+            H64, U256, U64,
         },
     };
     use hex_literal::hex;
@@ -917,4 +979,89 @@ mod tests {
       ::serde_json::from_str(EXAMPLE_PROOF).unwrap()
       => Some(::serde_json::from_str::<Proof>(EXAMPLE_PROOF).unwrap())
     }
+
+    /// Answers `eth_getLogs` with one log per block, refusing ranges wider than 4 blocks.
+    #[derive(Debug, Clone, Default)]
+    struct Paginated {
+        ranges: std::sync::Arc<parking_lot::Mutex<Vec<(u64, u64)>>>,
+    }
+
+    impl crate::Transport for Paginated {
+        type Out = futures::future::BoxFuture<'static, crate::error::Result<Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<Value>) -> (crate::RequestId, crate::rpc::Call) {
+            (1, crate::helpers::build_request(1, method, params))
+        }
+
+        fn send(&self, _id: crate::RequestId, request: crate::rpc::Call) -> Self::Out {
+            use futures::FutureExt;
+            let filter = match request {
+                crate::rpc::Call::MethodCall(crate::rpc::MethodCall {
+                    params: crate::rpc::Params::Array(params),
+                    ..
+                }) => params[0].clone(),
+                _ => unreachable!(),
+            };
+            let block = |key: &str| u64::from_str_radix(filter[key].as_str().unwrap().trim_start_matches("0x"), 16);
+            let (from, to) = (block("fromBlock").unwrap(), block("toBlock").unwrap());
+            self.ranges.lock().push((from, to));
+            let answer = if to - from >= 4 {
+                Err(crate::Error::Rpc(crate::rpc::Error {
+                    code: crate::rpc::ErrorCode::ServerError(-32005),
+                    message: "query returned more than 10000 results".into(),
+                    data: None,
+                }))
+            } else {
+                let log = |n: u64| {
+                    json!({"address": Address::zero(), "topics": [], "data": "0x", "blockNumber": U64::from(n)})
+                };
+                Ok(Value::Array((from..=to).map(log).collect()))
+            };
+            futures::future::ready(answer).boxed()
+        }
+    }
+
+    #[test]
+    fn logs_paginated_should_shrink_window() {
+        // given
+        let transport = Paginated::default();
+        let eth = Eth::new(transport.clone());
+
+        // when
+        let filter = FilterBuilder::default()
+            .from_block(BlockNumber::Number(1.into()))
+            .to_block(BlockNumber::Number(12.into()))
+            .build();
+        let logs = futures::executor::block_on(eth.logs_paginated(filter, 10)).unwrap();
+
+        // then
+        let blocks = logs.iter().map(|log| log.block_number.unwrap().as_u64()).collect::<Vec<_>>();
+        assert_eq!(blocks, (1..=12).collect::<Vec<_>>());
+        assert_eq!(
+            *transport.ranges.lock(),
+            vec![(1, 10), (1, 5), (1, 2), (3, 6), (7, 12), (7, 9), (10, 12)]
+        );
+    }
+
+    #[test]
+    fn logs_paginated_should_resolve_finalized_tag() {
+        // given
+        let mut transport = crate::transports::test::TestTransport::default();
+        transport.add_response(json!({ "number": "0x5" }));
+        transport.add_response(json!([]));
+        let eth = Eth::new(&transport);
+
+        // when
+        let filter = FilterBuilder::default()
+            .from_block(BlockNumber::Number(1.into()))
+            .to_block(BlockNumber::Finalized)
+            .build();
+        let logs = futures::executor::block_on(eth.logs_paginated(filter, 10)).unwrap();
+
+        // then
+        assert!(logs.is_empty());
+        transport.assert_request("eth_getBlockByNumber", &[r#""finalized""#.into(), "false".into()]);
+        transport.assert_request("eth_getLogs", &[r#"{"fromBlock":"0x1","toBlock":"0x5"}"#.into()]);
+        transport.assert_no_more_requests();
+    }
 }
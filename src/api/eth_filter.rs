@@ -2,14 +2,21 @@
 
 use crate::{
     api::Namespace,
-    error, helpers, rpc,
-    types::{Filter, Log, H256},
+    error, helpers, rpc, timer,
+    types::{Filter, Log, H256, U64},
     Transport,
 };
+use candid::CandidType;
 use futures::{stream, Stream, TryStreamExt};
 use futures_timer::Delay;
-use serde::de::DeserializeOwned;
-use std::{fmt, marker::PhantomData, time::Duration, vec};
+use parking_lot::Mutex;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::{fmt, marker::PhantomData, sync::Arc, time::Duration, vec};
+
+/// Blocks scanned by one poll of a logs [ScanFilter].
+const MAX_LOG_BLOCKS: u64 = 1_000;
+/// Blocks scanned by one poll of a blocks [ScanFilter], each one is a call.
+const MAX_BLOCKS: u64 = 32;
 
 fn filter_stream<T: Transport, I: DeserializeOwned>(
     base: BaseFilter<T, I>,
@@ -148,6 +155,165 @@ impl<T: Transport> BaseFilter<T, Log> {
     }
 }
 
+#[derive(Debug, Clone)]
+enum Scan {
+    Logs(rpc::Value),
+    Blocks,
+}
+
+/// Position of a [ScanFilter], to be persisted by the caller, e.g. across canister upgrades.
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanCursor {
+    /// The next block to scan.
+    pub next_block: u64,
+}
+
+/// Filter scanning block ranges with `eth_getLogs` and `eth_blockNumber`.
+///
+/// Unlike [BaseFilter] it keeps no state on the node, so it works across load-balanced providers
+/// and replicas. Clones share the cursor; it moves past the items of every poll.
+pub struct ScanFilter<T: Transport, I> {
+    scan: Scan,
+    transport: T,
+    cursor: Arc<Mutex<ScanCursor>>,
+    max_blocks: u64,
+    item: PhantomData<I>,
+}
+
+impl<T: Transport, I: 'static> fmt::Debug for ScanFilter<T, I> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.debug_struct("ScanFilter")
+            .field("scan", &self.scan)
+            .field("transport", &self.transport)
+            .field("cursor", &self.cursor)
+            .field("max_blocks", &self.max_blocks)
+            .field("item", &std::any::TypeId::of::<I>())
+            .finish()
+    }
+}
+
+impl<T: Transport, I> Clone for ScanFilter<T, I> {
+    fn clone(&self) -> Self {
+        ScanFilter {
+            scan: self.scan.clone(),
+            transport: self.transport.clone(),
+            cursor: self.cursor.clone(),
+            max_blocks: self.max_blocks,
+            item: PhantomData::default(),
+        }
+    }
+}
+
+impl<T: Transport, I> ScanFilter<T, I> {
+    /// Current position of the filter.
+    pub fn cursor(&self) -> ScanCursor {
+        *self.cursor.lock()
+    }
+
+    /// Set the most blocks scanned by one poll, the rest follow on the next polls.
+    pub fn set_max_blocks(&mut self, max_blocks: u64) {
+        self.max_blocks = max_blocks.max(1);
+    }
+
+    /// Borrows the transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+impl<T: Transport, I: DeserializeOwned> ScanFilter<T, I> {
+    /// Scans the blocks from the cursor on, up to the latest one.
+    /// Will return items that happened after previous poll.
+    pub async fn poll(&self) -> error::Result<Vec<I>> {
+        let from = self.cursor().next_block;
+        let latest = block_number(&self.transport).await?;
+        if latest < from {
+            return Ok(vec![]);
+        }
+        let to = latest.min(from + self.max_blocks - 1);
+        let (items, next_block) = match &self.scan {
+            Scan::Logs(filter) => {
+                let mut filter = filter.clone();
+                filter["fromBlock"] = helpers::serialize(&U64::from(from));
+                filter["toBlock"] = helpers::serialize(&U64::from(to));
+                let logs = self.transport.execute("eth_getLogs", vec![filter]).await?;
+                (helpers::decode(logs)?, to + 1)
+            }
+            Scan::Blocks => {
+                let mut hashes = vec![];
+                let mut next_block = to + 1;
+                for number in from..=to {
+                    let number = helpers::serialize(&U64::from(number));
+                    let block = self
+                        .transport
+                        .execute("eth_getBlockByNumber", vec![number, false.into()])
+                        .await?;
+                    match block.get("hash") {
+                        Some(hash) if !hash.is_null() => hashes.push(helpers::decode(hash.clone())?),
+                        // the provider is not there yet, or the block is still pending
+                        _ => {
+                            next_block = from + hashes.len() as u64;
+                            break;
+                        }
+                    }
+                }
+                (hashes, next_block)
+            }
+        };
+        self.cursor.lock().next_block = next_block;
+        Ok(items)
+    }
+
+    /// Returns the stream of items which automatically polls the server.
+    ///
+    /// Waits `poll_interval` with [timer::sleep] only after polls which found nothing, staying in
+    /// the call context of the canister method consuming the stream. Ends with the error of a
+    /// failed wait.
+    pub fn stream(self, poll_interval: Duration) -> impl Stream<Item = error::Result<I>> {
+        stream::unfold(Some((self, false)), move |state| async move {
+            let (scan, wait) = state?;
+            if wait {
+                if let Err(err) = timer::sleep(poll_interval).await {
+                    return Some((Err(err), None));
+                }
+            }
+            let items = scan.poll().await;
+            let wait = !matches!(items, Ok(ref items) if !items.is_empty());
+            Some((items, Some((scan, wait))))
+        })
+        .map_ok(|items| stream::iter(items.into_iter().map(Ok)))
+        .try_flatten()
+        .into_stream()
+    }
+}
+
+async fn block_number<T: Transport>(transport: &T) -> error::Result<u64> {
+    let number: U64 = helpers::decode(transport.execute("eth_blockNumber", vec![]).await?)?;
+    Ok(number.as_u64())
+}
+
+/// Should be used to create new scan filter future
+async fn create_scan<T: Transport, I>(
+    transport: T,
+    scan: Scan,
+    cursor: Option<ScanCursor>,
+    max_blocks: u64,
+) -> error::Result<ScanFilter<T, I>> {
+    let cursor = match cursor {
+        Some(cursor) => cursor,
+        None => ScanCursor {
+            next_block: block_number(&transport).await? + 1,
+        },
+    };
+    Ok(ScanFilter {
+        scan,
+        transport,
+        cursor: Arc::new(Mutex::new(cursor)),
+        max_blocks,
+        item: PhantomData,
+    })
+}
+
 /// Should be used to create new filter future
 async fn create_filter<T: Transport, F: FilterInterface>(
     transport: T,
@@ -197,11 +363,23 @@ impl<T: Transport> EthFilter<T> {
     pub async fn create_pending_transactions_filter(self) -> error::Result<BaseFilter<T, H256>> {
         create_filter::<_, PendingTransactionsFilter>(self.transport, vec![]).await
     }
+
+    /// Creates a logs filter scanning from `cursor`, or from the next block if `None`.
+    /// Stateless alternative to [Self::create_logs_filter].
+    pub async fn scan_logs(self, filter: Filter, cursor: Option<ScanCursor>) -> error::Result<ScanFilter<T, Log>> {
+        create_scan(self.transport, Scan::Logs(helpers::serialize(&filter)), cursor, MAX_LOG_BLOCKS).await
+    }
+
+    /// Creates a block hashes filter scanning from `cursor`, or from the next block if `None`.
+    /// Stateless alternative to [Self::create_blocks_filter].
+    pub async fn scan_blocks(self, cursor: Option<ScanCursor>) -> error::Result<ScanFilter<T, H256>> {
+        create_scan(self.transport, Scan::Blocks, cursor, MAX_BLOCKS).await
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::EthFilter;
+    use super::{EthFilter, ScanCursor};
     use crate::{
         api::Namespace,
         rpc::Value,
@@ -439,4 +617,82 @@ mod tests {
         transport.assert_request("eth_getFilterChanges", &[r#""0x123""#.into()]);
         transport.assert_no_more_requests();
     }
+
+    #[test]
+    fn scan_logs_poll() {
+        // given
+        let log = Log {
+            address: Address::from_low_u64_be(1),
+            topics: vec![],
+            data: hex!("").into(),
+            block_hash: Some(H256::from_low_u64_be(2)),
+            block_number: Some(0x11.into()),
+            transaction_hash: Some(H256::from_low_u64_be(3)),
+            transaction_index: Some(0.into()),
+            log_index: Some(0.into()),
+            transaction_log_index: Some(0.into()),
+            log_type: Some("mined".into()),
+            removed: None,
+        };
+
+        let mut transport = TestTransport::default();
+        transport.set_response(Value::String("0x12".into()));
+        transport.add_response(Value::Array(vec![serde_json::to_value(&log).unwrap()]));
+        transport.add_response(Value::String("0x12".into()));
+        let (result, cursor) = {
+            let eth = EthFilter::new(&transport);
+
+            // when
+            let filter = FilterBuilder::default().address(vec![Address::from_low_u64_be(1)]).build();
+            let cursor = ScanCursor { next_block: 0x11 };
+            let filter = futures::executor::block_on(eth.scan_logs(filter, Some(cursor))).unwrap();
+            let result = futures::executor::block_on(filter.poll());
+            assert_eq!(futures::executor::block_on(filter.poll()), Ok(vec![]));
+            (result, filter.cursor())
+        };
+
+        // then
+        assert_eq!(result, Ok(vec![log]));
+        assert_eq!(cursor, ScanCursor { next_block: 0x13 });
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request(
+            "eth_getLogs",
+            &[
+                r#"{"address":"0x0000000000000000000000000000000000000001","fromBlock":"0x11","toBlock":"0x12"}"#
+                    .into(),
+            ],
+        );
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_no_more_requests();
+    }
+
+    #[test]
+    fn scan_blocks_stream() {
+        // given
+        let mut transport = TestTransport::default();
+        transport.set_response(Value::String("0x10".into()));
+        transport.add_response(Value::String("0x12".into()));
+        transport.add_response(serde_json::json!({ "hash": H256::from_low_u64_be(0x456) }));
+        transport.add_response(Value::Null);
+        let (result, cursor) = {
+            let eth = EthFilter::new(&transport);
+
+            // when
+            let filter = futures::executor::block_on(eth.scan_blocks(None)).unwrap();
+            assert_eq!(filter.cursor(), ScanCursor { next_block: 0x11 });
+            let cursor = filter.clone();
+            let stream = filter.stream(Duration::from_secs(0));
+            let result = futures::executor::block_on(stream.take(1).collect::<Vec<_>>());
+            (result, cursor.cursor())
+        };
+
+        // then
+        assert_eq!(result, vec![Ok(H256::from_low_u64_be(0x456))]);
+        assert_eq!(cursor, ScanCursor { next_block: 0x12 });
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_getBlockByNumber", &[r#""0x11""#.into(), "false".into()]);
+        transport.assert_request("eth_getBlockByNumber", &[r#""0x12""#.into(), "false".into()]);
+        transport.assert_no_more_requests();
+    }
 }
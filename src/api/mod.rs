@@ -16,7 +16,7 @@ mod web3;
 pub use self::{
     accounts::Accounts,
     eth::Eth,
-    eth_filter::{BaseFilter, EthFilter},
+    eth_filter::{BaseFilter, EthFilter, ScanCursor, ScanFilter},
     eth_subscribe::{EthSubscribe, SubscriptionId, SubscriptionStream},
     net::Net,
     parity::Parity,
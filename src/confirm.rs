@@ -43,17 +43,26 @@ where
     V: ConfirmationCheck<Check = F>,
     F: Future<Output = error::Result<Option<U64>>>,
 {
-    let filter = eth_filter.create_blocks_filter().await?;
-    // TODO #396: The stream should have additional checks.
-    // * We should not continue calling next on a stream that has completed (has returned None). We expect this to never
-    //   happen for the blocks filter but to be safe we should handle this case for example by `fuse`ing the stream or
-    //   erroring when it does complete.
-    // * We do not handle the case where the stream returns an error which means we are wrongly counting it as a
-    //   confirmation.
-    let filter_stream = filter.stream(poll_interval).skip(confirmations);
+    // scans new blocks with `eth_blockNumber` and `eth_getBlockByNumber`, so it keeps working behind
+    // load-balanced providers and consensus transports, unlike a filter installed on one node
+    let filter = eth_filter.scan_blocks(None).await?;
+    // TODO #396: We do not handle the case where the stream returns an error which means we are wrongly counting it
+    // as a confirmation.
+    let filter_stream = filter.stream(poll_interval);
     futures::pin_mut!(filter_stream);
+    let mut last_error = None;
+    let mut skipped = 0;
     loop {
-        let _ = filter_stream.next().await;
+        match filter_stream.next().await {
+            // the stream only ends after yielding the error of a failed wait
+            None => return Err(last_error.unwrap_or(error::Error::Unreachable)),
+            Some(Err(err)) => last_error = Some(err),
+            Some(Ok(_)) => {}
+        }
+        if skipped < confirmations {
+            skipped += 1;
+            continue;
+        }
         if let Some(confirmation_block_number) = check.check().await? {
             let block_number = eth.block_number().await?;
             if confirmation_block_number.low_u64() + confirmations as u64 <= block_number.low_u64() {
@@ -166,30 +175,25 @@ mod tests {
         };
 
         let poll_interval = Duration::from_secs(0);
+        let block = |hash: u64| json!({ "hash": H256::from_low_u64_be(hash) });
         transport.add_response(Value::String(
             r#"0x0000000000000000000000000000000000000000000000000000000000000111"#.into(),
         ));
-        transport.add_response(Value::String("0x123".into()));
-        transport.add_response(Value::Array(vec![
-            Value::String(r#"0x0000000000000000000000000000000000000000000000000000000000000456"#.into()),
-            Value::String(r#"0x0000000000000000000000000000000000000000000000000000000000000457"#.into()),
-        ]));
-        transport.add_response(Value::Array(vec![Value::String(
-            r#"0x0000000000000000000000000000000000000000000000000000000000000458"#.into(),
-        )]));
-        transport.add_response(Value::Array(vec![Value::String(
-            r#"0x0000000000000000000000000000000000000000000000000000000000000459"#.into(),
-        )]));
-        transport.add_response(Value::Null);
-        transport.add_response(Value::Array(vec![
-            Value::String(r#"0x0000000000000000000000000000000000000000000000000000000000000460"#.into()),
-            Value::String(r#"0x0000000000000000000000000000000000000000000000000000000000000461"#.into()),
-        ]));
-        transport.add_response(Value::Null);
+        // the scan starts after block 1
+        transport.add_response(Value::String("0x1".into()));
+        transport.add_response(Value::String("0x3".into()));
+        transport.add_response(block(0x456));
+        transport.add_response(block(0x457));
+        // block 4 is still pending
+        transport.add_response(Value::String("0x4".into()));
+        transport.add_response(json!({ "hash": null }));
+        transport.add_response(Value::String("0x4".into()));
+        transport.add_response(block(0x458));
+        transport.add_response(Value::String("0x5".into()));
+        transport.add_response(block(0x459));
         transport.add_response(json!(transaction_receipt));
-        transport.add_response(Value::String("0x6".into()));
+        transport.add_response(Value::String("0x5".into()));
         transport.add_response(json!(transaction_receipt));
-        transport.add_response(Value::Bool(true));
 
         let confirmation = {
             let future =
@@ -198,19 +202,17 @@ mod tests {
         };
 
         transport.assert_request("eth_sendTransaction", &[r#"{"from":"0x0000000000000000000000000000000000000123","gasPrice":"0x1","to":"0x0000000000000000000000000000000000000123","value":"0x1"}"#.into()]);
-        transport.assert_request("eth_newBlockFilter", &[]);
-        transport.assert_request("eth_getFilterChanges", &[r#""0x123""#.into()]);
-        transport.assert_request("eth_getFilterChanges", &[r#""0x123""#.into()]);
-        transport.assert_request("eth_getFilterChanges", &[r#""0x123""#.into()]);
-        transport.assert_request(
-            "eth_getTransactionReceipt",
-            &[r#""0x0000000000000000000000000000000000000000000000000000000000000111""#.into()],
-        );
-        transport.assert_request("eth_getFilterChanges", &[r#""0x123""#.into()]);
-        transport.assert_request(
-            "eth_getTransactionReceipt",
-            &[r#""0x0000000000000000000000000000000000000000000000000000000000000111""#.into()],
-        );
+        let get_block = |number: &str| [format!(r#""{}""#, number), "false".into()];
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_getBlockByNumber", &get_block("0x2"));
+        transport.assert_request("eth_getBlockByNumber", &get_block("0x3"));
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_getBlockByNumber", &get_block("0x4"));
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_getBlockByNumber", &get_block("0x4"));
+        transport.assert_request("eth_blockNumber", &[]);
+        transport.assert_request("eth_getBlockByNumber", &get_block("0x5"));
         transport.assert_request(
             "eth_getTransactionReceipt",
             &[r#""0x0000000000000000000000000000000000000000000000000000000000000111""#.into()],
@@ -5,11 +5,10 @@ use crate::{
     confirm,
     contract::{tokens::Tokenize, Contract, Options},
     error,
-    types::{Address, Bytes, TransactionReceipt, TransactionRequest},
+    signing::Signer,
+    types::{Address, Bytes, TransactionParameters, TransactionReceipt, TransactionRequest},
     Transport,
 };
-#[cfg(feature = "signing")]
-use crate::{signing::Key, types::TransactionParameters};
 use futures::{Future, TryFutureExt};
 use std::{collections::HashMap, time};
 
@@ -99,43 +98,37 @@ impl<T: Transport> Builder<T> {
     /// Execute deployment passing code and constructor parameters.
     ///
     /// Unlike the above `sign_and_execute`, this method allows the
-    /// caller to pass in a private key to sign the transaction with
-    /// and therefore allows deploying from an account that the
-    /// ethereum node doesn't need to know the private key for.
+    /// caller to pass in any [Signer], e.g. the canister's threshold
+    /// ECDSA key, and therefore allows deploying from an account that
+    /// the ethereum node doesn't need to know the private key for.
     ///
-    /// An optional `chain_id` parameter can be passed to provide
-    /// replay protection for transaction signatures. Passing `None`
-    /// would create a transaction WITHOUT replay protection and
-    /// should be avoided.
-    /// You can obtain `chain_id` of the network you are connected
-    /// to using `web3.eth().chain_id()` method.
-    #[cfg(feature = "signing")]
-    pub async fn sign_with_key_and_execute<P, V, K>(
+    /// The chain id of the network is looked up with `eth_chainId`
+    /// when `chain_id` is `None`.
+    pub async fn sign_with_key_and_execute<P, V, S>(
         self,
         code: V,
         params: P,
-        from: K,
+        signer: &S,
         chain_id: Option<u64>,
     ) -> Result<Contract<T>, Error>
     where
         P: Tokenize,
         V: AsRef<str>,
-        K: Key,
+        S: Signer,
     {
         let transport = self.eth.transport().clone();
         let poll_interval = self.poll_interval;
         let confirmations = self.confirmations;
 
-        self.do_execute(code, params, from.address(), move |tx| async move {
+        self.do_execute(code, params, signer.address(), move |tx| async move {
             let tx = TransactionParameters {
                 nonce: tx.nonce,
                 to: tx.to,
-                gas: tx.gas.unwrap_or_else(|| 1_000_000.into()),
+                gas: tx.gas,
                 gas_price: tx.gas_price,
                 value: tx.value.unwrap_or_else(|| 0.into()),
-                data: tx
-                    .data
-                    .expect("Tried to deploy a contract but transaction data wasn't set"),
+                // always set by `do_execute`
+                data: tx.data.unwrap_or_default(),
                 chain_id,
                 transaction_type: tx.transaction_type,
                 access_list: tx.access_list,
@@ -143,7 +136,7 @@ impl<T: Transport> Builder<T> {
                 max_priority_fee_per_gas: tx.max_priority_fee_per_gas,
             };
             let signed_tx = crate::api::Accounts::new(transport.clone())
-                .sign_transaction(tx, from)
+                .sign_transaction(tx, signer)
                 .await?;
             confirm::send_raw_transaction_with_confirmation(
                 transport,
@@ -6,11 +6,10 @@ use crate::{
     contract::tokens::{Detokenize, Tokenize},
     futures::Future,
     types::{
-        AccessList, Address, BlockId, Bytes, CallRequest, FilterBuilder, TransactionCondition, TransactionReceipt,
-        TransactionRequest, H256, U256, U64,
+        AccessList, Address, BlockId, BlockNumber, Bytes, CallRequest, FilterBuilder, Log, TransactionCondition,
+        TransactionReceipt, TransactionRequest, H256, U256, U64,
     },
     Transport,
-    ic::KeyInfo,
 };
 use std::{collections::HashMap, hash::Hash, time};
 
@@ -24,6 +23,9 @@ pub use crate::contract::error::Error;
 /// Contract `Result` type.
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Blocks per `eth_getLogs` query of [Contract::events_paginated], the range limit of common providers.
+const EVENTS_WINDOW: u64 = 10_000;
+
 /// Contract Call/Query Options
 #[derive(Default, Debug, Clone, PartialEq)]
 pub struct Options {
@@ -286,6 +288,52 @@ impl<T: Transport> Contract<T> {
         B: Tokenize,
         C: Tokenize,
         R: Detokenize,
+    {
+        let (ev, filter) = self.event_filter(event, topic0, topic1, topic2)?;
+        let logs = self
+            .eth
+            .logs(FilterBuilder::default().topic_filter(filter).build())
+            .await?;
+        parse_events(&ev, logs)
+    }
+
+    /// Find events of this contract matching the topics within a block range, querying the
+    /// range in windows which shrink whenever the provider refuses one as too large.
+    pub async fn events_paginated<A, B, C, R>(
+        &self,
+        event: &str,
+        topics: (A, B, C),
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+    ) -> Result<Vec<R>>
+    where
+        A: Tokenize,
+        B: Tokenize,
+        C: Tokenize,
+        R: Detokenize,
+    {
+        let (ev, filter) = self.event_filter(event, topics.0, topics.1, topics.2)?;
+        let filter = FilterBuilder::default()
+            .address(vec![self.address])
+            .topic_filter(filter)
+            .from_block(from_block)
+            .to_block(to_block)
+            .build();
+        let logs = self.eth.logs_paginated(filter, EVENTS_WINDOW).await?;
+        parse_events(&ev, logs)
+    }
+
+    fn event_filter<A, B, C>(
+        &self,
+        event: &str,
+        topic0: A,
+        topic1: B,
+        topic2: C,
+    ) -> Result<(ethabi::Event, ethabi::TopicFilter)>
+    where
+        A: Tokenize,
+        B: Tokenize,
+        C: Tokenize,
     {
         fn to_topic<A: Tokenize>(x: A) -> ethabi::Topic<ethabi::Token> {
             let tokens = x.into_tokens();
@@ -296,34 +344,27 @@ impl<T: Transport> Contract<T> {
             }
         }
 
-        let res = self.abi.event(event).and_then(|ev| {
-            let filter = ev.filter(ethabi::RawTopicFilter {
-                topic0: to_topic(topic0),
-                topic1: to_topic(topic1),
-                topic2: to_topic(topic2),
+        let ev = self.abi.event(event)?;
+        let filter = ev.filter(ethabi::RawTopicFilter {
+            topic0: to_topic(topic0),
+            topic1: to_topic(topic1),
+            topic2: to_topic(topic2),
+        })?;
+        Ok((ev.clone(), filter))
+    }
+}
+
+fn parse_events<R: Detokenize>(ev: &ethabi::Event, logs: Vec<Log>) -> Result<Vec<R>> {
+    logs.into_iter()
+        .map(move |l| {
+            let log = ev.parse_log(ethabi::RawLog {
+                topics: l.topics,
+                data: l.data.0,
             })?;
-            Ok((ev.clone(), filter))
-        });
-        let (ev, filter) = match res {
-            Ok(x) => x,
-            Err(e) => return Err(e.into()),
-        };
 
-        let logs = self
-            .eth
-            .logs(FilterBuilder::default().topic_filter(filter).build())
-            .await?;
-        logs.into_iter()
-            .map(move |l| {
-                let log = ev.parse_log(ethabi::RawLog {
-                    topics: l.topics,
-                    data: l.data.0,
-                })?;
-
-                R::from_tokens(log.params.into_iter().map(|x| x.value).collect::<Vec<_>>())
-            })
-            .collect::<Result<Vec<R>>>()
-    }
+            R::from_tokens(log.params.into_iter().map(|x| x.value).collect::<Vec<_>>())
+        })
+        .collect::<Result<Vec<R>>>()
 }
 
 // #[cfg(feature = "signing")]
@@ -331,19 +372,36 @@ mod contract_signing {
     use super::*;
     use crate::{
         api::Accounts,
+        signing::Signer,
         types::{SignedTransaction, TransactionParameters},
+        BatchTransport,
     };
 
     impl<T: Transport> Contract<T> {
-        pub async fn sign(
+        /// Sign a contract call transaction with any [Signer].
+        ///
+        /// Missing parameters are looked up, including the chain id when `chain_id` is `None`, with
+        /// [Accounts::sign_transaction] and thus one request each. See [Contract::sign_batched].
+        pub async fn sign<S: Signer>(
             &self,
             func: &str,
             params: impl Tokenize,
             options: Options,
-            from: String,
-            key_info: KeyInfo,
-            chain_id: u64,
+            signer: &S,
+            chain_id: Option<u64>,
         ) -> crate::Result<SignedTransaction> {
+            let tx = self.transaction(func, params, options, chain_id)?;
+            let accounts = Accounts::new(self.eth.transport().clone());
+            accounts.sign_transaction(tx, signer).await
+        }
+
+        fn transaction(
+            &self,
+            func: &str,
+            params: impl Tokenize,
+            options: Options,
+            chain_id: Option<u64>,
+        ) -> crate::Result<TransactionParameters> {
             let fn_data = self
                 .abi
                 .function(func)
@@ -351,41 +409,38 @@ mod contract_signing {
                 // TODO [ToDr] SendTransactionWithConfirmation should support custom error type (so that we can return
                 // `contract::Error` instead of more generic `Error`.
                 .map_err(|err| crate::error::Error::Decoder(format!("{:?}", err)))?;
-            let accounts = Accounts::new(self.eth.transport().clone());
             let mut tx = TransactionParameters {
                 nonce: options.nonce,
                 to: Some(self.address),
+                gas: options.gas,
                 gas_price: options.gas_price,
                 data: Bytes(fn_data),
                 transaction_type: options.transaction_type,
                 access_list: options.access_list,
                 max_fee_per_gas: options.max_fee_per_gas,
                 max_priority_fee_per_gas: options.max_priority_fee_per_gas,
+                chain_id,
                 ..Default::default()
             };
-            if let Some(gas) = options.gas {
-                tx.gas = gas;
-            }
             if let Some(value) = options.value {
                 tx.value = value;
             }
-            accounts.sign_transaction(tx, from, key_info, chain_id).await
+            Ok(tx)
         }
 
         /// Submit contract call transaction to the transaction pool.
         ///
         /// Note this function DOES NOT wait for any confirmations, so there is no guarantees that the call is actually executed.
         /// If you'd rather wait for block inclusion, please use [`signed_call_with_confirmations`] instead.
-        pub async fn signed_call(
+        pub async fn signed_call<S: Signer>(
             &self,
             func: &str,
             params: impl Tokenize,
             options: Options,
-            from: String,
-            key_info: KeyInfo,
-            chain_id: u64,
+            signer: &S,
+            chain_id: Option<u64>,
         ) -> crate::Result<H256> {
-            let signed = self.sign(func, params, options, from, key_info, chain_id).await?;
+            let signed = self.sign(func, params, options, signer, chain_id).await?;
             self.eth.send_raw_transaction(signed.raw_transaction).await
         }
 
@@ -393,18 +448,17 @@ mod contract_signing {
         //
         // This function will wait for block inclusion of the transaction before returning.
         // If you'd rather just submit transaction and receive it's hash, please use [`signed_call`] instead.
-        pub async fn signed_call_with_confirmations(
+        pub async fn signed_call_with_confirmations<S: Signer>(
             &self,
             func: &str,
             params: impl Tokenize,
             options: Options,
-            from: String,
             confirmations: usize,
-            key_info: KeyInfo,
-            chain_id: u64,
+            signer: &S,
+            chain_id: Option<u64>,
         ) -> crate::Result<TransactionReceipt> {
             let poll_interval = time::Duration::from_secs(1);
-            let signed = self.sign(func, params, options, from, key_info, chain_id).await?;
+            let signed = self.sign(func, params, options, signer, chain_id).await?;
 
             confirm::send_raw_transaction_with_confirmation(
                 self.eth.transport().clone(),
@@ -415,6 +469,23 @@ mod contract_signing {
             .await
         }
     }
+
+    impl<T: BatchTransport> Contract<T> {
+        /// Sign a contract call transaction like [Contract::sign], looking up missing parameters
+        /// in a single batch with [Accounts::sign_transaction_batched].
+        pub async fn sign_batched<S: Signer>(
+            &self,
+            func: &str,
+            params: impl Tokenize,
+            options: Options,
+            signer: &S,
+            chain_id: Option<u64>,
+        ) -> crate::Result<SignedTransaction> {
+            let tx = self.transaction(func, params, options, chain_id)?;
+            let accounts = Accounts::new(self.eth.transport().clone());
+            accounts.sign_transaction_batched(tx, signer).await
+        }
+    }
 }
 
 #[cfg(test)]
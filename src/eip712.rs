@@ -0,0 +1,413 @@
+//! EIP-712 typed structured data hashing and signing.
+
+use crate::{
+    error,
+    signing::{self, Signer},
+    types::{Address, Bytes, Recovery, SignedData, H256, U256},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Error while encoding typed data.
+#[derive(Debug, derive_more::Display, PartialEq, Clone)]
+pub enum Eip712Error {
+    /// A type is neither an atomic, dynamic nor array type, nor defined in `types`.
+    #[display(fmt = "Unknown type {}.", _0)]
+    UnknownType(String),
+    /// A struct value lacks a field of its type.
+    #[display(fmt = "Missing field {} of {}.", _1, _0)]
+    MissingField(String, String),
+    /// A value does not match its type.
+    #[display(fmt = "Invalid {} value {}.", _0, _1)]
+    InvalidValue(String, Value),
+}
+impl std::error::Error for Eip712Error {}
+
+/// A field of a struct type.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct TypedField {
+    /// Name of the field.
+    pub name: String,
+    /// Type of the field, e.g. `address`, `uint256[]` or the name of another struct type.
+    #[serde(rename = "type")]
+    pub r#type: String,
+}
+
+/// Typed data as passed to `eth_signTypedData_v4`.
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    /// Struct types by name. `EIP712Domain` may be left out, it is then derived from `domain`.
+    pub types: BTreeMap<String, Vec<TypedField>>,
+    /// Name of the type of `message`.
+    pub primary_type: String,
+    /// Values of the `EIP712Domain` fields.
+    pub domain: Value,
+    /// The struct to sign.
+    pub message: Value,
+}
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// Fields an `EIP712Domain` may have, in their canonical order.
+const DOMAIN_FIELDS: &[(&str, &str)] = &[
+    ("name", "string"),
+    ("version", "string"),
+    ("chainId", "uint256"),
+    ("verifyingContract", "address"),
+    ("salt", "bytes32"),
+];
+
+impl TypedData {
+    /// Fields of the struct type `name`.
+    fn fields(&self, name: &str) -> Option<std::borrow::Cow<'_, [TypedField]>> {
+        if let Some(fields) = self.types.get(name) {
+            return Some(fields.as_slice().into());
+        }
+        if name != DOMAIN_TYPE {
+            return None;
+        }
+        let fields = DOMAIN_FIELDS
+            .iter()
+            .filter(|(name, _)| self.domain.get(name).is_some())
+            .map(|(name, r#type)| TypedField {
+                name: name.to_string(),
+                r#type: r#type.to_string(),
+            })
+            .collect::<Vec<_>>();
+        Some(fields.into())
+    }
+
+    /// Collects the struct types `name` references, including itself.
+    fn dependencies(&self, name: &str, found: &mut BTreeSet<String>) -> Result<(), Eip712Error> {
+        let name = element_type(name);
+        if found.contains(name) || !self.is_struct(name) {
+            return Ok(());
+        }
+        found.insert(name.to_string());
+        for field in self.fields(name).unwrap_or_default().iter() {
+            let r#type = element_type(&field.r#type);
+            if !self.is_struct(r#type) && !is_builtin(r#type) {
+                return Err(Eip712Error::UnknownType(field.r#type.clone()));
+            }
+            self.dependencies(r#type, found)?;
+        }
+        Ok(())
+    }
+
+    fn is_struct(&self, name: &str) -> bool {
+        self.types.contains_key(name) || name == DOMAIN_TYPE
+    }
+
+    /// The `encodeType` of struct type `name`, e.g.
+    /// `Mail(Person from,Person to,string contents)Person(string name,address wallet)`.
+    pub fn encode_type(&self, name: &str) -> Result<String, Eip712Error> {
+        if !self.is_struct(name) {
+            return Err(Eip712Error::UnknownType(name.to_string()));
+        }
+        let mut dependencies = BTreeSet::new();
+        self.dependencies(name, &mut dependencies)?;
+        dependencies.remove(name);
+
+        let mut encoded = String::new();
+        for name in std::iter::once(name).chain(dependencies.iter().map(String::as_str)) {
+            let fields = self.fields(name).unwrap_or_default();
+            let fields = fields
+                .iter()
+                .map(|field| format!("{} {}", field.r#type, field.name))
+                .collect::<Vec<_>>();
+            encoded.push_str(&format!("{}({})", name, fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    /// The `typeHash` of struct type `name`.
+    pub fn type_hash(&self, name: &str) -> Result<H256, Eip712Error> {
+        Ok(signing::keccak256(self.encode_type(name)?.as_bytes()).into())
+    }
+
+    /// The `hashStruct` of `value` as struct type `name`.
+    pub fn hash_struct(&self, name: &str, value: &Value) -> Result<H256, Eip712Error> {
+        let fields = self
+            .fields(name)
+            .ok_or_else(|| Eip712Error::UnknownType(name.to_string()))?;
+        let mut encoded = self.type_hash(name)?.as_bytes().to_vec();
+        for field in fields.iter() {
+            let value = value
+                .get(&field.name)
+                .ok_or_else(|| Eip712Error::MissingField(name.to_string(), field.name.clone()))?;
+            encoded.extend_from_slice(&self.encode_value(&field.r#type, value)?);
+        }
+        Ok(signing::keccak256(&encoded).into())
+    }
+
+    /// Encodes a field value into its 32 bytes of `encodeData`.
+    fn encode_value(&self, r#type: &str, value: &Value) -> Result<[u8; 32], Eip712Error> {
+        let invalid = || Eip712Error::InvalidValue(r#type.to_string(), value.clone());
+
+        if r#type.ends_with(']') {
+            let elements = value.as_array().ok_or_else(invalid)?;
+            let open = r#type.rfind('[').ok_or_else(|| Eip712Error::UnknownType(r#type.to_string()))?;
+            let length = &r#type[open + 1..r#type.len() - 1];
+            if !length.is_empty() && length.parse::<usize>().ok() != Some(elements.len()) {
+                return Err(invalid());
+            }
+            let mut encoded = Vec::with_capacity(32 * elements.len());
+            for element in elements {
+                encoded.extend_from_slice(&self.encode_value(&r#type[..open], element)?);
+            }
+            return Ok(signing::keccak256(&encoded));
+        }
+        if self.is_struct(r#type) {
+            return Ok(self.hash_struct(r#type, value)?.0);
+        }
+
+        let mut encoded = [0u8; 32];
+        match r#type {
+            "string" => encoded = signing::keccak256(value.as_str().ok_or_else(invalid)?.as_bytes()),
+            "bytes" => encoded = signing::keccak256(&decode_hex(value).ok_or_else(invalid)?),
+            "bool" => encoded[31] = value.as_bool().ok_or_else(invalid)? as u8,
+            "address" => {
+                let address: Address = serde_json::from_value(value.clone()).map_err(|_| invalid())?;
+                encoded[12..].copy_from_slice(address.as_bytes());
+            }
+            _ => {
+                if let Some(size) = r#type.strip_prefix("bytes").and_then(|size| size.parse::<usize>().ok()) {
+                    let bytes = decode_hex(value).ok_or_else(invalid)?;
+                    if !(1..=32).contains(&size) || bytes.len() > size {
+                        return Err(invalid());
+                    }
+                    encoded[..bytes.len()].copy_from_slice(&bytes);
+                } else if let Some(bits) = integer_bits(r#type, "uint") {
+                    let (negative, magnitude) = parse_integer(value).ok_or_else(invalid)?;
+                    if negative || magnitude.bits() > bits {
+                        return Err(invalid());
+                    }
+                    magnitude.to_big_endian(&mut encoded);
+                } else if let Some(bits) = integer_bits(r#type, "int") {
+                    let (negative, magnitude) = parse_integer(value).ok_or_else(invalid)?;
+                    let limit = U256::one() << (bits - 1);
+                    if (negative && magnitude > limit) || (!negative && magnitude >= limit) {
+                        return Err(invalid());
+                    }
+                    let value = if negative {
+                        (!magnitude).overflowing_add(U256::one()).0
+                    } else {
+                        magnitude
+                    };
+                    value.to_big_endian(&mut encoded);
+                } else {
+                    return Err(Eip712Error::UnknownType(r#type.to_string()));
+                }
+            }
+        }
+        Ok(encoded)
+    }
+
+    /// The domain separator, i.e. the `hashStruct` of `domain`.
+    pub fn domain_separator(&self) -> Result<H256, Eip712Error> {
+        self.hash_struct(DOMAIN_TYPE, &self.domain)
+    }
+
+    /// The data signed: `"\x19\x01" || domainSeparator || hashStruct(message)`.
+    pub fn encode(&self) -> Result<Vec<u8>, Eip712Error> {
+        let mut encoded = vec![0x19, 0x01];
+        encoded.extend_from_slice(self.domain_separator()?.as_bytes());
+        encoded.extend_from_slice(self.hash_struct(&self.primary_type, &self.message)?.as_bytes());
+        Ok(encoded)
+    }
+
+    /// The hash signed, the keccak256 of [TypedData::encode].
+    pub fn signing_hash(&self) -> Result<H256, Eip712Error> {
+        Ok(signing::keccak256(&self.encode()?).into())
+    }
+
+    /// Signs the typed data with any [Signer], e.g. the canister's threshold ECDSA key.
+    ///
+    /// The signature is 65 bytes `r || s || v` with `v` either `27` or `28`, as returned by
+    /// `eth_signTypedData_v4` and accepted by `ecrecover`.
+    pub async fn sign<S: Signer>(&self, signer: &S) -> error::Result<SignedData> {
+        let message = self.encode()?;
+        let message_hash = signing::keccak256(&message);
+
+        let signature = signer.sign_hash(message_hash).await?;
+        let (signature, recovery_id) = signing::recoverable(&message_hash, &signature, signer.address())?;
+        let v = 27 + recovery_id as u8;
+
+        let mut signature_bytes = signature.to_vec();
+        signature_bytes.push(v);
+
+        Ok(SignedData {
+            message,
+            message_hash: message_hash.into(),
+            v,
+            r: H256::from_slice(&signature[..32]),
+            s: H256::from_slice(&signature[32..]),
+            signature: Bytes(signature_bytes),
+        })
+    }
+
+    /// Recovers the address which signed the typed data, given a 65-byte `r || s || v` signature.
+    pub fn recover(&self, signature: &[u8]) -> error::Result<Address> {
+        let hash = self.signing_hash()?;
+        let (signature, recovery_id) = Recovery::from_raw_signature(hash, signature)
+            .ok()
+            .and_then(|recovery| recovery.as_signature())
+            .ok_or(error::Error::Recovery(signing::RecoveryError::InvalidSignature))?;
+        Ok(signing::recover(hash.as_bytes(), &signature, recovery_id)?)
+    }
+
+    /// Whether `signature` of the typed data was made by `address`.
+    pub fn verify(&self, signature: &[u8], address: Address) -> error::Result<bool> {
+        Ok(self.recover(signature)? == address)
+    }
+}
+
+/// Strips array suffixes, e.g. `Person` of `Person[][2]`.
+fn element_type(r#type: &str) -> &str {
+    r#type.find('[').map_or(r#type, |open| &r#type[..open])
+}
+
+fn is_builtin(r#type: &str) -> bool {
+    matches!(r#type, "string" | "bytes" | "bool" | "address")
+        || r#type.strip_prefix("bytes").map_or(false, |size| size.parse::<usize>().is_ok())
+        || integer_bits(r#type, "uint").is_some()
+        || integer_bits(r#type, "int").is_some()
+}
+
+/// Bits of an `intN`/`uintN` type, `int` and `uint` being 256 bits.
+fn integer_bits(r#type: &str, prefix: &str) -> Option<usize> {
+    match r#type.strip_prefix(prefix)? {
+        "" => Some(256),
+        bits => bits.parse().ok().filter(|bits| bits % 8 == 0 && (8..=256).contains(bits)),
+    }
+}
+
+/// Parses a JSON number, or a decimal or `0x`-prefixed hex string, into sign and magnitude.
+fn parse_integer(value: &Value) -> Option<(bool, U256)> {
+    if let Some(value) = value.as_u64() {
+        return Some((false, value.into()));
+    }
+    if let Some(value) = value.as_i64() {
+        return Some((value < 0, value.unsigned_abs().into()));
+    }
+    let value = value.as_str()?;
+    let (negative, value) = match value.strip_prefix('-') {
+        Some(value) => (true, value),
+        None => (false, value),
+    };
+    let magnitude = match value.strip_prefix("0x") {
+        Some(hex) => U256::from_str_radix(hex, 16).ok()?,
+        None => U256::from_dec_str(value).ok()?,
+    };
+    Some((negative && !magnitude.is_zero(), magnitude))
+}
+
+fn decode_hex(value: &Value) -> Option<Vec<u8>> {
+    let value = value.as_str()?;
+    hex::decode(value.strip_prefix("0x").unwrap_or(value)).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::LocalSigner;
+    use hex_literal::hex;
+    use serde_json::json;
+
+    /// The example of the EIP.
+    fn mail() -> TypedData {
+        serde_json::from_value(json!({
+            "types": {
+                "EIP712Domain": [
+                    { "name": "name", "type": "string" },
+                    { "name": "version", "type": "string" },
+                    { "name": "chainId", "type": "uint256" },
+                    { "name": "verifyingContract", "type": "address" }
+                ],
+                "Person": [
+                    { "name": "name", "type": "string" },
+                    { "name": "wallet", "type": "address" }
+                ],
+                "Mail": [
+                    { "name": "from", "type": "Person" },
+                    { "name": "to", "type": "Person" },
+                    { "name": "contents", "type": "string" }
+                ]
+            },
+            "primaryType": "Mail",
+            "domain": {
+                "name": "Ether Mail",
+                "version": "1",
+                "chainId": 1,
+                "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+            },
+            "message": {
+                "from": { "name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826" },
+                "to": { "name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB" },
+                "contents": "Hello, Bob!"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn should_hash_typed_data() {
+        let data = mail();
+        assert_eq!(
+            data.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            data.domain_separator().unwrap(),
+            hex!("f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f").into()
+        );
+        assert_eq!(
+            data.hash_struct("Mail", &data.message).unwrap(),
+            hex!("c52c0ee5d84264471806290a3f2c4cecfc5490626bf912d01f240d7a274b371e").into()
+        );
+        assert_eq!(
+            data.signing_hash().unwrap(),
+            hex!("be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2").into()
+        );
+
+        // the domain type is derived from the domain when left out
+        let mut derived = data.clone();
+        derived.types.remove("EIP712Domain");
+        assert_eq!(derived.domain_separator(), data.domain_separator());
+    }
+
+    #[test]
+    fn should_sign_and_recover_typed_data() {
+        let data = mail();
+        let key = LocalSigner::new(&signing::keccak256(b"cow")).unwrap();
+        assert_eq!(key.address(), hex!("CD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826").into());
+
+        let signed = futures::executor::block_on(data.sign(&key)).unwrap();
+        assert_eq!(signed.v, 28);
+        assert_eq!(
+            signed.r,
+            hex!("4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d").into()
+        );
+        assert_eq!(
+            signed.s,
+            hex!("07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562").into()
+        );
+        assert_eq!(data.recover(&signed.signature.0), Ok(key.address()));
+        assert_eq!(data.verify(&signed.signature.0, Address::zero()), Ok(false));
+    }
+
+    #[test]
+    fn should_refuse_invalid_values() {
+        let mut data = mail();
+        data.message["from"]["wallet"] = json!("0x1234");
+        assert_eq!(
+            data.signing_hash(),
+            Err(Eip712Error::InvalidValue("address".into(), json!("0x1234")))
+        );
+
+        data.types.get_mut("Mail").unwrap()[2].r#type = "Text".into();
+        assert_eq!(data.encode_type("Mail"), Err(Eip712Error::UnknownType("Text".into())));
+    }
+}
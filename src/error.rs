@@ -1,6 +1,10 @@
 //! Web3 Error
-use crate::rpc::error::Error as RPCError;
+use crate::{
+    rpc::{error::Error as RPCError, Value as RpcValue},
+    transports::evm_rpc::RpcService,
+};
 use derive_more::{Display, From};
+use ic_cdk::api::call::RejectionCode;
 use serde_json::Error as SerdeError;
 use std::io::Error as IoError;
 
@@ -10,12 +14,18 @@ pub type Result<T = ()> = std::result::Result<T, Error>;
 /// Transport-depended error.
 #[derive(Display, Debug, Clone, PartialEq)]
 pub enum TransportError {
-    /// Transport-specific error code.
-    #[display(fmt = "code {}", _0)]
-    Code(u16),
+    /// Transport-specific error code, with an excerpt of the response body.
+    #[display(fmt = "code {}: {}", _0, _1)]
+    Code(u16, String),
+    /// The provider answered HTTP 429, with an excerpt of the response body.
+    #[display(fmt = "rate limited: {}", _0)]
+    RateLimited(String),
     /// Arbitrary, developer-readable description of the occurred error.
     #[display(fmt = "{}", _0)]
     Message(String),
+    /// The IC rejected the outcall, e.g. on timeouts or unreachable hosts.
+    #[display(fmt = "rejected with {:?}: {}", _0, _1)]
+    Rejected(RejectionCode, String),
 }
 
 /// Errors which can occur when attempting to generate resource uri.
@@ -44,19 +54,85 @@ pub enum Error {
     /// recovery error
     #[display(fmt = "Recovery error: {}", _0)]
     Recovery(crate::signing::RecoveryError),
+    /// signing error
+    #[display(fmt = "Signing error: {}", _0)]
+    Signing(crate::signing::SigningError),
+    /// typed data encoding error
+    #[display(fmt = "EIP-712 error: {}", _0)]
+    Eip712(crate::eip712::Eip712Error),
+    /// providers did not reach the required quorum, holds each provider's answer in order
+    #[display(fmt = "Providers disagree: {:?}", _0)]
+    #[from(ignore)]
+    Inconsistent(Vec<Result<RpcValue>>),
+    /// providers of the EVM RPC canister disagreed, holds each provider with its answer
+    #[display(fmt = "Providers disagree: {:?}", _0)]
+    #[from(ignore)]
+    InconsistentServices(Vec<(RpcService, Result<RpcValue>)>),
     /// web3 internal error
     #[display(fmt = "Internal Web3 error")]
     Internal,
 }
 
+impl Error {
+    /// Whether the provider refused the call because of a rate limit or an exhausted quota.
+    ///
+    /// Queries refused as too large are not rate limited, even though providers like Infura
+    /// answer both with `-32005`.
+    pub fn is_rate_limited(&self) -> bool {
+        match self {
+            Error::Rpc(_) if self.is_too_large() => false,
+            Error::Rpc(err) => {
+                let message = err.message.to_lowercase();
+                matches!(err.code.code(), -32005 | 429)
+                    || message.contains("rate limit")
+                    || message.contains("too many requests")
+            }
+            Error::Transport(TransportError::RateLimited(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether the provider refused a query as too large, e.g. an `eth_getLogs` block range with
+    /// too many results, or the response exceeded the outcall size limit.
+    pub fn is_too_large(&self) -> bool {
+        match self {
+            Error::Rpc(err) => {
+                let message = err.message.to_lowercase();
+                TOO_LARGE.iter().any(|pattern| message.contains(pattern))
+            }
+            err => crate::transports::response_size::is_oversize(err),
+        }
+    }
+}
+
+/// Messages of providers refusing `eth_getLogs` ranges.
+const TOO_LARGE: &[&str] = &[
+    "returned more than",
+    "block range",
+    "range too large",
+    "range is too large",
+    "range is too wide",
+    "is limited to",
+    "response size",
+    "too many results",
+];
+
 impl std::error::Error for Error {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         use self::Error::*;
         match *self {
-            Unreachable | Decoder(_) | InvalidResponse(_) | Transport { .. } | Internal => None,
+            Unreachable
+            | Decoder(_)
+            | InvalidResponse(_)
+            | Transport { .. }
+            | Inconsistent(_)
+            | InconsistentServices(_)
+            | Internal => None,
             Rpc(ref e) => Some(e),
             Io(ref e) => Some(e),
             Recovery(ref e) => Some(e),
+            Signing(ref e) => Some(e),
+            Eip712(ref e) => Some(e),
         }
     }
 }
@@ -78,6 +154,10 @@ impl Clone for Error {
             Rpc(e) => Rpc(e.clone()),
             Io(e) => Io(IoError::from(e.kind())),
             Recovery(e) => Recovery(e.clone()),
+            Signing(e) => Signing(e.clone()),
+            Eip712(e) => Eip712(e.clone()),
+            Inconsistent(answers) => Inconsistent(answers.clone()),
+            InconsistentServices(answers) => InconsistentServices(answers.clone()),
             Internal => Internal,
         }
     }
@@ -94,6 +174,10 @@ impl PartialEq for Error {
             (Rpc(a), Rpc(b)) => a == b,
             (Io(a), Io(b)) => a.kind() == b.kind(),
             (Recovery(a), Recovery(b)) => a == b,
+            (Signing(a), Signing(b)) => a == b,
+            (Eip712(a), Eip712(b)) => a == b,
+            (Inconsistent(a), Inconsistent(b)) => a == b,
+            (InconsistentServices(a), InconsistentServices(b)) => a == b,
             _ => false,
         }
     }
@@ -5,10 +5,11 @@ use ic_cdk::export::{
     serde::{Deserialize, Serialize},
     Principal,
 };
-use std::str::FromStr;
+use std::convert::TryInto;
 use crate::types::{Address, Recovery};
-use crate::signing;
-use libsecp256k1::{PublicKey, PublicKeyFormat, Message, Signature, RecoveryId, recover};
+use crate::signing::{self, RecoveryError, Signer, SigningError};
+use futures::future::{FutureExt, LocalBoxFuture};
+use libsecp256k1::{PublicKey, PublicKeyFormat};
 
 const ECDSA_SIGN_CYCLES : u64 = 10_000_000_000;
 // pub type Address = [u8; 20];
@@ -39,8 +40,7 @@ pub async fn get_public_key(
         curve: EcdsaCurve::Secp256k1,
         name: key_name,
     };
-    let ic_canister_id = "aaaaa-aa";
-    let ic = Principal::from_str(&ic_canister_id).unwrap();
+    let ic = Principal::management_canister();
 
 
     let request = EcdsaPublicKeyArgument {
@@ -85,7 +85,9 @@ pub async fn ic_raw_sign(
     message: Vec<u8>,
     key_info: KeyInfo,
 ) -> Result<Vec<u8>, String> {
-    assert!(message.len() == 32);
+    if message.len() != 32 {
+        return Err("message to sign has to be 32 bytes long".to_string());
+    }
 
     let key_id = EcdsaKeyId {
         curve: EcdsaCurve::Secp256k1,
@@ -109,32 +111,61 @@ pub async fn ic_raw_sign(
     Ok(res.signature)
 }
 
+/// Signer using a threshold ECDSA key of the canister.
+#[derive(Debug, Clone)]
+pub struct IcSigner {
+    key_info: KeyInfo,
+    address: Address,
+}
 
-// recover address from signature
-// rec_id < 4
-pub fn recover_address(msg: Vec<u8>, sig: Vec<u8>, rec_id: u8) -> String {
-    let message = Message::parse_slice(&msg).unwrap();
-    let signature = Signature::parse_overflowing_slice(&sig).unwrap();
-    let recovery_id = RecoveryId::parse(rec_id).unwrap();
-
-    match recover(&message, &signature, &recovery_id) {
-        Ok(pubkey) => {
-            let uncompressed_pubkey = pubkey.serialize();
-            // let hash = keccak256_hash(&uncompressed_pubkey[1..65]);
-            let hash = signing::keccak256(&uncompressed_pubkey[1..65]);
-            let mut result = [0u8; 20];
-            result.copy_from_slice(&hash[12..]);
-            hex::encode(result)
-        },
-        Err(_) => { "".into() }
+impl IcSigner {
+    /// Create a signer for `key_info`, fetching the public key of the canister.
+    pub async fn new(key_info: KeyInfo) -> Result<Self, String> {
+        let public_key = get_public_key(None, key_info.derivation_path.clone(), key_info.key_name.clone()).await?;
+        Self::from_public_key(key_info, &public_key)
     }
+
+    /// Create a signer for `key_info` from its known compressed public key, e.g. one kept in
+    /// canister state, without calling the management canister.
+    pub fn from_public_key(key_info: KeyInfo, public_key: &[u8]) -> Result<Self, String> {
+        Ok(IcSigner {
+            address: pubkey_to_address(public_key)?,
+            key_info,
+        })
+    }
+}
+
+impl Signer for IcSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign_hash(&self, hash: [u8; 32]) -> LocalBoxFuture<'_, Result<[u8; 64], SigningError>> {
+        let key_info = self.key_info.clone();
+        async move {
+            let signature = ic_raw_sign(hash.to_vec(), key_info).await.map_err(SigningError::Failed)?;
+            signature
+                .try_into()
+                .map_err(|_| SigningError::Failed("signature is not 64 bytes long".into()))
+        }
+        .boxed_local()
+    }
+}
+
+/// Recover the hex address, without `0x` prefix, which signed `msg`.
+/// rec_id < 4
+pub fn recover_address(msg: Vec<u8>, sig: Vec<u8>, rec_id: u8) -> Result<String, RecoveryError> {
+    let address = signing::recover(&msg, &sig, rec_id as i32)?;
+    Ok(hex::encode(address))
 }
 
-pub fn verify(addr: String, message: Vec<u8>, signature: Vec<u8>) -> bool {
+/// Verify that the 65-byte `signature` of the `message` hash, with `v` of 27 or 28, was made by
+/// `addr`. The address is compared case-insensitively, with or without `0x` prefix.
+pub fn verify(addr: String, message: Vec<u8>, signature: Vec<u8>) -> Result<bool, RecoveryError> {
     let (sig, rec_id) = Recovery::from_raw_signature(message.clone(), signature)
-        .unwrap()
+        .map_err(|_| RecoveryError::InvalidSignature)?
         .as_signature()
-        .unwrap();
-    let rec_addr = recover_address(message, sig.to_vec(), rec_id as u8);
-    return rec_addr == addr;
+        .ok_or(RecoveryError::InvalidSignature)?;
+    let rec_addr = recover_address(message, sig.to_vec(), rec_id as u8)?;
+    Ok(rec_addr.eq_ignore_ascii_case(addr.trim_start_matches("0x")))
 }
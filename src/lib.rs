@@ -25,6 +25,9 @@ pub use futures::executor::{block_on, block_on_stream};
 
 pub use ethabi;
 
+#[doc(hidden)]
+pub use ic_cdk_macros;
+
 // it needs to be before other modules
 // otherwise the macro for tests is not available.
 #[macro_use]
@@ -33,11 +36,14 @@ pub mod helpers;
 pub mod api;
 pub mod confirm;
 pub mod contract;
+pub mod eip712;
 pub mod error;
 pub mod signing;
 pub mod transports;
 pub mod types;
 pub mod ic;
+pub mod timer;
+pub mod transforms;
 // pub mod tx_helpers;
 
 pub use crate::{
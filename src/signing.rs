@@ -1,6 +1,7 @@
 //! Signing capabilities and utilities.
 
-use crate::types::H256;
+use crate::types::{Address, H256};
+use futures::future::{self, FutureExt, LocalBoxFuture};
 
 /// Error during signing.
 #[derive(Debug, derive_more::Display, PartialEq, Clone)]
@@ -8,6 +9,22 @@ pub enum SigningError {
     /// A message to sign is invalid. Has to be a non-zero 32-bytes slice.
     #[display(fmt = "Message has to be a non-zero 32-bytes slice.")]
     InvalidMessage,
+    /// A secret key is invalid. Has to be a non-zero 32-bytes slice below the curve order.
+    #[display(fmt = "Secret key is invalid.")]
+    InvalidKey,
+    /// The key source failed to sign, e.g. a rejected threshold ECDSA call.
+    #[display(fmt = "Signing failed: {}", _0)]
+    Failed(String),
+    /// The signature returned by the signer is not a valid secp256k1 signature.
+    #[display(fmt = "Signature is invalid.")]
+    InvalidSignature,
+    /// The signature does not recover to the address of the signer, e.g. a signer reporting the
+    /// wrong derivation path.
+    #[display(fmt = "Signature does not recover to the signer's address.")]
+    AddressMismatch,
+    /// A transaction to sign has a type which cannot be encoded.
+    #[display(fmt = "Unsupported transaction type {}.", _0)]
+    UnsupportedTransactionType(u64),
 }
 impl std::error::Error for SigningError {}
 
@@ -172,6 +189,106 @@ impl std::error::Error for RecoveryError {}
 //     }
 // }
 
+/// A source of secp256k1 signatures for an Ethereum account.
+///
+/// Implemented by [IcSigner](crate::ic::IcSigner) for the canister's threshold ECDSA key and by
+/// [LocalSigner] for an in-memory secret key, e.g. in tests.
+pub trait Signer {
+    /// Address of the signing key.
+    fn address(&self) -> Address;
+
+    /// Signs a 32-byte hash, returning the 64-byte `r || s` signature.
+    fn sign_hash(&self, hash: [u8; 32]) -> LocalBoxFuture<'_, Result<[u8; 64], SigningError>>;
+}
+
+impl<S: Signer + ?Sized> Signer for &S {
+    fn address(&self) -> Address {
+        (**self).address()
+    }
+
+    fn sign_hash(&self, hash: [u8; 32]) -> LocalBoxFuture<'_, Result<[u8; 64], SigningError>> {
+        (**self).sign_hash(hash)
+    }
+}
+
+/// Signer holding a secret key in memory.
+///
+/// The key stays in canister memory, readable by anyone controlling the canister. Prefer
+/// [IcSigner](crate::ic::IcSigner) outside of tests.
+#[derive(Clone)]
+pub struct LocalSigner {
+    key: libsecp256k1::SecretKey,
+    address: Address,
+}
+
+impl LocalSigner {
+    /// Create a signer from a 32-byte secret key.
+    pub fn new(key: &[u8]) -> Result<Self, SigningError> {
+        let key = libsecp256k1::SecretKey::parse_slice(key).map_err(|_| SigningError::InvalidKey)?;
+        let address = public_key_address(&libsecp256k1::PublicKey::from_secret_key(&key));
+        Ok(LocalSigner { key, address })
+    }
+}
+
+impl std::fmt::Debug for LocalSigner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LocalSigner").field("address", &self.address).finish()
+    }
+}
+
+impl Signer for LocalSigner {
+    fn address(&self) -> Address {
+        self.address
+    }
+
+    fn sign_hash(&self, hash: [u8; 32]) -> LocalBoxFuture<'_, Result<[u8; 64], SigningError>> {
+        let (signature, _) = libsecp256k1::sign(&libsecp256k1::Message::parse(&hash), &self.key);
+        future::ready(Ok(signature.serialize())).boxed_local()
+    }
+}
+
+/// Recover a sender, given message and the signature.
+///
+/// Signature and `recovery_id` can be obtained from `types::Recovery` type.
+pub fn recover(message: &[u8], signature: &[u8], recovery_id: i32) -> Result<Address, RecoveryError> {
+    let message = libsecp256k1::Message::parse_slice(message).map_err(|_| RecoveryError::InvalidMessage)?;
+    let recovery_id = match recovery_id {
+        0..=3 => libsecp256k1::RecoveryId::parse(recovery_id as u8).map_err(|_| RecoveryError::InvalidSignature)?,
+        _ => return Err(RecoveryError::InvalidSignature),
+    };
+    let signature =
+        libsecp256k1::Signature::parse_standard_slice(signature).map_err(|_| RecoveryError::InvalidSignature)?;
+    let public_key =
+        libsecp256k1::recover(&message, &signature, &recovery_id).map_err(|_| RecoveryError::InvalidSignature)?;
+    Ok(public_key_address(&public_key))
+}
+
+/// Normalise a signature of `hash` by `address` to low-s form and find its recovery id.
+///
+/// Signers may return either of the two valid `s` values, while EIP-2 only accepts the lower one.
+/// The recovery id is the one recovering to `address`; a signature recovering to neither is
+/// refused rather than returned with a wrong `v`.
+pub fn recoverable(hash: &[u8; 32], signature: &[u8; 64], address: Address) -> Result<([u8; 64], i32), SigningError> {
+    let mut signature =
+        libsecp256k1::Signature::parse_standard_slice(signature).map_err(|_| SigningError::InvalidSignature)?;
+    signature.normalize_s();
+    let signature = signature.serialize();
+    (0..2)
+        .find(|&recovery_id| recover(hash, &signature, recovery_id) == Ok(address))
+        .map(|recovery_id| (signature, recovery_id))
+        .ok_or(SigningError::AddressMismatch)
+}
+
+/// Gets the address of a public key.
+///
+/// The public address is defined as the low 20 bytes of the keccak hash of the uncompressed
+/// public key, without its `0x04` prefix.
+pub fn public_key_address(public_key: &libsecp256k1::PublicKey) -> Address {
+    let public_key = public_key.serialize();
+    let hash = keccak256(&public_key[1..]);
+    Address::from_slice(&hash[12..])
+}
+
 /// A struct that represents the components of a secp256k1 signature.
 pub struct Signature {
     /// V component in electrum format with chain-id replay protection.
@@ -235,6 +352,49 @@ where
     keccak256(&eth_message).into()
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hex_literal::hex;
+
+    #[test]
+    fn local_signer_should_sign_recoverable_hashes() {
+        let key = hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318");
+        let signer = LocalSigner::new(&key).unwrap();
+        assert_eq!(signer.address(), hex!("2c7536E3605D9C16a7a3D7b1898e529396a65c23").into());
+
+        let hash = hash_message("Some data");
+        let signature = futures::executor::block_on(signer.sign_hash(hash.0)).unwrap();
+        let recovered = (0..2).map(|id| recover(hash.as_bytes(), &signature, id)).collect::<Vec<_>>();
+        assert!(recovered.contains(&Ok(signer.address())));
+    }
+
+    #[test]
+    fn recoverable_should_normalise_high_s() {
+        let key = hex!("4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318");
+        let signer = LocalSigner::new(&key).unwrap();
+        let hash = hash_message("Some data").0;
+        let low = futures::executor::block_on(signer.sign_hash(hash)).unwrap();
+        let (signature, recovery_id) = recoverable(&hash, &low, signer.address()).unwrap();
+        assert_eq!(signature, low);
+
+        // n - s, the high-s twin of the same signature
+        let mut high = libsecp256k1::Signature::parse_standard(&low).unwrap();
+        high.s = -high.s;
+        let high = high.serialize();
+        assert_ne!(high, low);
+        assert_eq!(recoverable(&hash, &high, signer.address()), Ok((low, recovery_id)));
+
+        assert_eq!(recoverable(&hash, &low, Address::zero()), Err(SigningError::AddressMismatch));
+    }
+
+    #[test]
+    fn local_signer_should_refuse_invalid_keys() {
+        assert_eq!(LocalSigner::new(&[0u8; 32]).unwrap_err(), SigningError::InvalidKey);
+        assert_eq!(LocalSigner::new(&[1u8; 31]).unwrap_err(), SigningError::InvalidKey);
+    }
+}
+
 // #[cfg(test)]
 // mod tests {
 //     use super::*;
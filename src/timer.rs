@@ -0,0 +1,31 @@
+//! Canister timers
+//!
+//! `futures-timer` relies on a background thread, which canisters do not have. The helpers here
+//! wait on the IC instead.
+
+use crate::error::{Error, Result, TransportError};
+use ic_cdk::export::Principal;
+use std::time::Duration;
+
+/// Resolves once `duration` has passed, or fails with the rejection of the first call which failed.
+///
+/// Waits by calling `raw_rand` of the management canister until the deadline, each call taking
+/// at least a round. The waiting future thus resumes in the context of the canister method which
+/// awaits it, which can still reply, unlike one woken by an IC timer running as its own message.
+/// Costs an inter-canister call per round waited. Other messages are processed in the meantime.
+/// Only works inside a canister, except for a zero `duration` which resolves right away. Always
+/// fails in queries, which cannot make inter-canister calls.
+pub async fn sleep(duration: Duration) -> Result<()> {
+    if duration.is_zero() {
+        return Ok(());
+    }
+    let deadline = ic_cdk::api::time().saturating_add(duration.as_nanos().min(u64::MAX as u128) as u64);
+    while ic_cdk::api::time() < deadline {
+        let waited: ic_cdk::api::call::CallResult<(Vec<u8>,)> =
+            ic_cdk::call(Principal::management_canister(), "raw_rand", ()).await;
+        if let Err((code, message)) = waited {
+            return Err(Error::Transport(TransportError::Rejected(code, message)));
+        }
+    }
+    Ok(())
+}
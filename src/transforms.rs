@@ -0,0 +1,364 @@
+//! Deterministic transforms for JSON-RPC outcall responses.
+//!
+//! Every replica of a subnet performs the outcall on its own and the responses have to be
+//! identical to reach consensus. The functions here strip headers, canonicalise the JSON body
+//! (sorted keys, no whitespace) and drop fields known to vary between nodes, depending on the
+//! JSON-RPC method of the call.
+//!
+//! Submissions of raw transactions are normalised as well: whether a provider accepted the
+//! transaction or answered that it already knows it, every replica sees the transaction hash,
+//! see [ICHttp::set_raw_transaction_mode](crate::transports::ICHttp::set_raw_transaction_mode).
+//!
+//! Register them as the transform query of the canister with [register_transform], and let
+//! `ICHttp` tell the transform which call it cleans up with [rpc_transform_hook]:
+//!
+//! ```ignore
+//! ic_web3::register_transform!();
+//!
+//! let mut http = ICHttp::new(URL, None)?;
+//! http.set_transform_hook(ic_web3::transforms::rpc_transform_hook);
+//! ```
+
+use crate::{
+    error::{Error, Result},
+    rpc, signing,
+    transports::ic_http_client::Transform,
+    types::H256,
+};
+pub use ic_cdk::api::management_canister::http_request::{HttpResponse, TransformArgs};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Name of the query method registered by [register_transform].
+pub const TRANSFORM_METHOD: &str = "transform";
+
+/// JSON-RPC method submitting a signed transaction.
+pub const SEND_RAW_TRANSACTION: &str = "eth_sendRawTransaction";
+
+/// Error messages providers answer when a transaction was already submitted, e.g. by another
+/// replica of the subnet.
+const DUPLICATE_SUBMISSION: &[&str] = &[
+    "already known",
+    "alreadyknown",
+    "known transaction",
+    "already imported",
+    "nonce too low",
+];
+
+/// Context handed to the transform, naming the JSON-RPC method of each call of the outcall.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct RpcContext {
+    /// Request id and method of every call, a single entry unless the outcall is a batch.
+    pub calls: Vec<(u64, String)>,
+    /// Request id and locally computed hash of every `eth_sendRawTransaction` call.
+    #[serde(default)]
+    pub raw_transactions: Vec<(u64, H256)>,
+}
+
+impl RpcContext {
+    /// Collects the methods of the calls in a request.
+    pub fn from_request(request: &rpc::Request) -> Self {
+        let calls = match request {
+            rpc::Request::Single(call) => vec![call],
+            rpc::Request::Batch(calls) => calls.iter().collect(),
+        };
+        let mut context = RpcContext::default();
+        for call in calls {
+            if let rpc::Call::MethodCall(call) = call {
+                let id = match call.id {
+                    rpc::Id::Num(id) => id,
+                    _ => 0,
+                };
+                context.calls.push((id, call.method.clone()));
+                if let Some(hash) = raw_transaction_hash(call) {
+                    context.raw_transactions.push((id, hash));
+                }
+            }
+        }
+        context
+    }
+
+    /// Serializes the context into transform context bytes.
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(self).expect("context never fails to serialize")
+    }
+
+    /// Parses transform context bytes, an empty or unknown context yields no methods.
+    pub fn decode(bytes: &[u8]) -> Self {
+        serde_json::from_slice(bytes).unwrap_or_default()
+    }
+
+    fn method(&self, id: Option<u64>) -> Option<&str> {
+        match (id, self.calls.as_slice()) {
+            (_, [(_, method)]) => Some(method.as_str()),
+            (Some(id), calls) => calls.iter().find(|(i, _)| *i == id).map(|(_, m)| m.as_str()),
+            _ => None,
+        }
+    }
+
+    /// Hash of the raw transaction submitted by the call with the given id, if any.
+    pub fn raw_transaction(&self, id: Option<u64>) -> Option<H256> {
+        match (id, self.calls.as_slice()) {
+            (_, [_]) => self.raw_transactions.first().map(|(_, hash)| *hash),
+            (Some(id), _) => self.raw_transactions.iter().find(|(i, _)| *i == id).map(|(_, h)| *h),
+            _ => None,
+        }
+    }
+}
+
+/// Hash of the transaction submitted by an `eth_sendRawTransaction` call, computed locally.
+pub fn raw_transaction_hash(call: &rpc::MethodCall) -> Option<H256> {
+    if call.method != SEND_RAW_TRANSACTION {
+        return None;
+    }
+    let raw = match &call.params {
+        rpc::Params::Array(params) => params.first()?.as_str()?,
+        _ => return None,
+    };
+    let bytes = hex::decode(raw.trim_start_matches("0x")).ok()?;
+    Some(signing::keccak256(&bytes).into())
+}
+
+/// Whether an error message means the transaction was already submitted.
+pub fn is_duplicate_submission(message: &str) -> bool {
+    let message = message.to_lowercase();
+    DUPLICATE_SUBMISSION.iter().any(|known| message.contains(known))
+}
+
+/// Maps the answer to a raw transaction submission to its locally computed hash.
+///
+/// Accepted submissions and duplicate submission errors both yield `hash`, any other error is
+/// kept.
+///
+/// The hash does not prove the transaction was accepted: "nonce too low" also comes back when
+/// another transaction already used the nonce, and then this one is never mined. Callers have to
+/// check the receipt, or the nonce of the sender, rather than wait on the hash alone.
+pub fn normalize_raw_transaction_result(result: Result<rpc::Value>, hash: H256) -> Result<rpc::Value> {
+    match result {
+        Ok(_) => Ok(serde_json::to_value(hash)?),
+        Err(Error::Rpc(err)) if is_duplicate_submission(&err.message) => Ok(serde_json::to_value(hash)?),
+        Err(err) => Err(err),
+    }
+}
+
+/// Transform hook for `ICHttp` sending each outcall to [TRANSFORM_METHOD] with its [RpcContext].
+pub fn rpc_transform_hook(request: &rpc::Request) -> Option<Transform> {
+    Some(Transform::new(TRANSFORM_METHOD, RpcContext::from_request(request).encode()))
+}
+
+/// Fields dropped from the result of a JSON-RPC method because they vary between nodes.
+pub fn volatile_fields(method: &str) -> &'static [&'static str] {
+    match method {
+        "eth_getBlockByNumber" | "eth_getBlockByHash" | "eth_getUncleByBlockHashAndIndex" => {
+            &["totalDifficulty", "size"]
+        }
+        "eth_getLogs" | "eth_getFilterLogs" | "eth_getFilterChanges" | "eth_getTransactionReceipt" => &["removed"],
+        _ => &[],
+    }
+}
+
+/// Strips all headers of the response, keeping status and body.
+pub fn strip_headers(response: HttpResponse) -> HttpResponse {
+    HttpResponse {
+        status: response.status,
+        headers: vec![],
+        body: response.body,
+    }
+}
+
+/// Cleans up a JSON-RPC response body.
+///
+/// Drops the volatile fields of the called method and the provider specific `data` of errors,
+/// then re-serializes the body with sorted keys and without whitespace. Bodies which are not
+/// JSON are returned unchanged.
+pub fn sanitize_body(body: &[u8], context: &RpcContext) -> Vec<u8> {
+    let mut value: Value = match serde_json::from_slice(body) {
+        Ok(value) => value,
+        Err(_) => return body.to_vec(),
+    };
+    match value {
+        Value::Array(ref mut outputs) => outputs.iter_mut().for_each(|output| sanitize_output(output, context)),
+        ref mut output => sanitize_output(output, context),
+    }
+    serde_json::to_vec(&sort_keys(value)).expect("value never fails to serialize")
+}
+
+/// Rebuilds objects with sorted keys, whichever map `serde_json` is compiled with.
+fn sort_keys(value: Value) -> Value {
+    match value {
+        Value::Object(map) => {
+            let mut entries = map.into_iter().collect::<Vec<_>>();
+            entries.sort_by(|(a, _), (b, _)| a.cmp(b));
+            Value::Object(entries.into_iter().map(|(k, v)| (k, sort_keys(v))).collect())
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(sort_keys).collect()),
+        value => value,
+    }
+}
+
+fn sanitize_output(output: &mut Value, context: &RpcContext) {
+    let output = match output.as_object_mut() {
+        Some(output) => output,
+        None => return,
+    };
+    if let Some(error) = output.get_mut("error").and_then(Value::as_object_mut) {
+        error.remove("data");
+    }
+    let id = output.get("id").and_then(Value::as_u64);
+    if let Some(hash) = context.raw_transaction(id) {
+        let duplicate = output
+            .get("error")
+            .and_then(|error| error.get("message"))
+            .and_then(Value::as_str)
+            .map(is_duplicate_submission)
+            .unwrap_or(false);
+        if duplicate || output.contains_key("result") {
+            output.remove("error");
+            output.insert("result".into(), serde_json::to_value(hash).expect("hash never fails to serialize"));
+        }
+        return;
+    }
+    if let (Some(method), Some(result)) = (context.method(id), output.get_mut("result")) {
+        drop_fields(result, volatile_fields(method));
+    }
+}
+
+fn drop_fields(value: &mut Value, fields: &[&str]) {
+    match value {
+        Value::Object(map) => {
+            for field in fields {
+                map.remove(*field);
+            }
+            map.values_mut().for_each(|v| drop_fields(v, fields));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| drop_fields(v, fields)),
+        _ => {}
+    }
+}
+
+/// Transform for JSON-RPC outcalls: strips headers and sanitizes the body by method.
+pub fn rpc_transform(args: TransformArgs) -> HttpResponse {
+    let context = RpcContext::decode(&args.context);
+    let mut response = strip_headers(args.response);
+    response.body = sanitize_body(&response.body, &context);
+    response
+}
+
+/// Registers [rpc_transform] as the `transform` query method of the canister.
+///
+/// The canister has to depend on `ic-cdk`, and its candid interface has to declare
+/// `transform : (TransformArgs) -> (HttpResponse) query`.
+#[macro_export]
+macro_rules! register_transform {
+    () => {
+        #[$crate::ic_cdk_macros::query(name = "transform")]
+        fn transform(args: $crate::transforms::TransformArgs) -> $crate::transforms::HttpResponse {
+            $crate::transforms::rpc_transform(args)
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context(calls: &[(u64, &str)]) -> RpcContext {
+        RpcContext {
+            calls: calls.iter().map(|(id, m)| (*id, m.to_string())).collect(),
+            raw_transactions: vec![],
+        }
+    }
+
+    #[test]
+    fn should_canonicalize_body() {
+        let body = br#"{ "result": "0x1",  "jsonrpc": "2.0", "id": 1 }"#;
+        let sanitized = sanitize_body(body, &RpcContext::default());
+        assert_eq!(sanitized, br#"{"id":1,"jsonrpc":"2.0","result":"0x1"}"#.to_vec());
+    }
+
+    #[test]
+    fn should_drop_error_data() {
+        let body = br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"reverted","data":"trace-42"}}"#;
+        let sanitized = sanitize_body(body, &RpcContext::default());
+        assert_eq!(
+            sanitized,
+            br#"{"error":{"code":-32000,"message":"reverted"},"id":1,"jsonrpc":"2.0"}"#.to_vec()
+        );
+    }
+
+    #[test]
+    fn should_drop_volatile_fields_by_method() {
+        let body = br#"[
+            {"jsonrpc":"2.0","id":2,"result":[{"logIndex":"0x0","removed":false}]},
+            {"jsonrpc":"2.0","id":1,"result":{"number":"0x1","totalDifficulty":"0x5","size":"0x10"}}
+        ]"#;
+        let context = context(&[(1, "eth_getBlockByNumber"), (2, "eth_getLogs")]);
+        let sanitized = sanitize_body(body, &context);
+        assert_eq!(
+            String::from_utf8(sanitized).unwrap(),
+            r#"[{"id":2,"jsonrpc":"2.0","result":[{"logIndex":"0x0"}]},{"id":1,"jsonrpc":"2.0","result":{"number":"0x1"}}]"#
+        );
+    }
+
+    #[test]
+    fn should_keep_non_json_body() {
+        let body = b"502 Bad Gateway";
+        assert_eq!(sanitize_body(body, &RpcContext::default()), body.to_vec());
+    }
+
+    #[test]
+    fn should_round_trip_context() {
+        let request = rpc::Request::Single(crate::helpers::build_request(7, "eth_getLogs", vec![]));
+        let context = RpcContext::from_request(&request);
+        assert_eq!(context, self::context(&[(7, "eth_getLogs")]));
+        assert_eq!(RpcContext::decode(&context.encode()), context);
+        assert_eq!(RpcContext::decode(&[]), RpcContext::default());
+    }
+
+    #[test]
+    fn should_hash_raw_transactions() {
+        let call = crate::helpers::build_request(3, SEND_RAW_TRANSACTION, vec!["0x01ff".into()]);
+        let context = RpcContext::from_request(&rpc::Request::Single(call));
+        let hash = H256::from(signing::keccak256(&[0x01, 0xff]));
+        assert_eq!(context.raw_transactions, vec![(3, hash)]);
+        assert_eq!(context.raw_transaction(Some(3)), Some(hash));
+    }
+
+    #[test]
+    fn should_normalize_duplicate_submissions() {
+        let call = crate::helpers::build_request(1, SEND_RAW_TRANSACTION, vec!["0x01".into()]);
+        let context = RpcContext::from_request(&rpc::Request::Single(call));
+        let hash = serde_json::to_string(&H256::from(signing::keccak256(&[0x01]))).unwrap();
+        let expected = format!(r#"{{"id":1,"jsonrpc":"2.0","result":{}}}"#, hash);
+
+        let accepted = br#"{"jsonrpc":"2.0","id":1,"result":"0x1234"}"#;
+        let known = br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"already known"}}"#;
+        let nonce = br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"nonce too low"}}"#;
+        for body in [&accepted[..], &known[..], &nonce[..]] {
+            assert_eq!(String::from_utf8(sanitize_body(body, &context)).unwrap(), expected);
+        }
+
+        let underpriced = br#"{"jsonrpc":"2.0","id":1,"error":{"code":-32000,"message":"underpriced"}}"#;
+        assert!(String::from_utf8(sanitize_body(underpriced, &context)).unwrap().contains("underpriced"));
+    }
+
+    #[test]
+    fn should_normalize_raw_transaction_results() {
+        let hash = H256::repeat_byte(7);
+        let mut known = rpc::Error::new(rpc::ErrorCode::ServerError(-32000));
+        known.message = "Known transaction: 0707".into();
+
+        assert_eq!(
+            normalize_raw_transaction_result(Err(Error::Rpc(known)), hash),
+            Ok(serde_json::to_value(hash).unwrap())
+        );
+        assert_eq!(
+            normalize_raw_transaction_result(Ok("0x07".into()), hash),
+            Ok(serde_json::to_value(hash).unwrap())
+        );
+        assert_eq!(
+            normalize_raw_transaction_result(Err(Error::Unreachable), hash),
+            Err(Error::Unreachable)
+        );
+    }
+}
@@ -2,10 +2,13 @@
 
 use crate::{
     error::{self, Error},
-    rpc, BatchTransport, RequestId, Transport,
+    rpc,
+    transports::ResponseSizePolicy,
+    BatchTransport, RequestId, Transport,
 };
 use futures::{
     channel::oneshot,
+    future,
     task::{Context, Poll},
     Future, FutureExt,
 };
@@ -14,13 +17,20 @@ use std::{collections::BTreeMap, pin::Pin, sync::Arc};
 
 type Pending = oneshot::Sender<error::Result<rpc::Value>>;
 type PendingRequests = Arc<Mutex<BTreeMap<RequestId, Pending>>>;
+type Chunk = Vec<(RequestId, rpc::Call)>;
 
 /// Transport allowing to batch queries together.
+///
+/// Queued calls are split into chunks of at most `max_batch_size` calls, and of an estimated
+/// response not exceeding the cap of the response size policy, each sent as its own batch.
 #[derive(Debug, Clone)]
 pub struct Batch<T> {
     transport: T,
     pending: PendingRequests,
-    batch: Arc<Mutex<Vec<(RequestId, rpc::Call)>>>,
+    batch: Arc<Mutex<Chunk>>,
+    max_batch_size: Option<usize>,
+    size_policy: Option<ResponseSizePolicy>,
+    concurrent: bool,
 }
 
 impl<T> Batch<T>
@@ -33,31 +43,101 @@ where
             transport,
             pending: Default::default(),
             batch: Default::default(),
+            max_batch_size: None,
+            size_policy: None,
+            concurrent: false,
         }
     }
 
-    /// Sends all requests as a batch.
+    /// Set the largest number of calls sent in one batch, e.g. the provider's batch limit.
+    pub fn set_max_batch_size(&mut self, count: usize) {
+        self.max_batch_size = Some(count.max(1));
+    }
+
+    /// Split batches whose estimated response exceeds the cap of `policy`.
+    pub fn set_response_size_policy(&mut self, policy: ResponseSizePolicy) {
+        self.size_policy = Some(policy);
+    }
+
+    /// Send the chunks of a batch concurrently instead of one after another.
+    pub fn set_concurrent(&mut self, concurrent: bool) {
+        self.concurrent = concurrent;
+    }
+
+    fn chunks(&self, batch: Chunk) -> Vec<Chunk> {
+        let mut chunks: Vec<Chunk> = vec![];
+        let mut bytes = 0;
+        for (id, call) in batch {
+            let size = self.size_policy.as_ref().map(|p| p.for_call(&call)).unwrap_or(0);
+            let full = match chunks.last() {
+                Some(chunk) => {
+                    self.max_batch_size.map_or(false, |max| chunk.len() >= max)
+                        || self.size_policy.as_ref().map_or(false, |p| bytes + size > p.cap())
+                }
+                None => true,
+            };
+            if full {
+                chunks.push(vec![]);
+                bytes = 0;
+            }
+            bytes += size;
+            chunks.last_mut().expect("pushed above").push((id, call));
+        }
+        chunks
+    }
+
+    /// Sends all requests as a batch, split into chunks if configured.
+    ///
+    /// Results come in the order the calls were queued. A chunk failing as a whole fails each
+    /// of its calls; the batch fails only if every chunk did.
     pub fn submit_batch(&self) -> impl Future<Output = error::Result<Vec<error::Result<rpc::Value>>>> {
         let batch = std::mem::take(&mut *self.batch.lock());
-        let ids = batch.iter().map(|&(id, _)| id).collect::<Vec<_>>();
+        let chunks = self.chunks(batch);
 
-        let batch = self.transport.send_batch(batch);
+        let transport = self.transport.clone();
+        let concurrent = self.concurrent;
         let pending = self.pending.clone();
 
         async move {
-            let res = batch.await;
+            let answers = if concurrent {
+                future::join_all(chunks.iter().map(|chunk| transport.send_batch(chunk.clone()))).await
+            } else {
+                let mut answers = Vec::with_capacity(chunks.len());
+                for chunk in &chunks {
+                    answers.push(transport.send_batch(chunk.clone()).await);
+                }
+                answers
+            };
+
             let mut pending = pending.lock();
-            for (idx, request_id) in ids.into_iter().enumerate() {
+            let mut results = vec![];
+            let mut failure = None;
+            let mut succeeded = false;
+            for (chunk, answer) in chunks.iter().zip(answers) {
+                match answer {
+                    Ok(answer) => {
+                        succeeded = true;
+                        for idx in 0..chunk.len() {
+                            results.push(answer.get(idx).cloned().unwrap_or(Err(Error::Internal)));
+                        }
+                    }
+                    Err(err) => {
+                        results.extend(chunk.iter().map(|_| Err(err.clone())));
+                        failure.get_or_insert(err);
+                    }
+                }
+            }
+            let ids = chunks.iter().flatten().map(|&(id, _)| id);
+            for (request_id, result) in ids.zip(results.iter()) {
                 if let Some(rx) = pending.remove(&request_id) {
                     // Ignore sending error
-                    let _ = match res {
-                        Ok(ref results) if results.len() > idx => rx.send(results[idx].clone()),
-                        Err(ref err) => rx.send(Err(err.clone())),
-                        _ => rx.send(Err(Error::Internal)),
-                    };
+                    let _ = rx.send(result.clone());
                 }
             }
-            res
+            match failure {
+                Some(err) if !succeeded => Err(err),
+                _ => Ok(results),
+            }
         }
     }
 }
@@ -96,3 +176,105 @@ impl Future for SingleResult {
         Poll::Ready(ready!(self.0.poll_unpin(ctx)).map_err(|_| Error::Internal)?)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers;
+    use futures::{executor::block_on, future::BoxFuture};
+
+    /// Answers calls with their id, failing batches containing `eth_fail`. Records the size of
+    /// every batch.
+    #[derive(Debug, Clone, Default)]
+    struct Chunked {
+        sizes: Arc<Mutex<Vec<usize>>>,
+    }
+
+    impl Transport for Chunked {
+        type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+            (1, helpers::build_request(1, method, params))
+        }
+
+        fn send(&self, _id: RequestId, _request: rpc::Call) -> Self::Out {
+            future::ready(Err(Error::Unreachable)).boxed()
+        }
+    }
+
+    impl BatchTransport for Chunked {
+        type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+        fn send_batch<I>(&self, requests: I) -> Self::Batch
+        where
+            I: IntoIterator<Item = (RequestId, rpc::Call)>,
+        {
+            let requests = requests.into_iter().collect::<Vec<_>>();
+            self.sizes.lock().push(requests.len());
+            let failed = requests.iter().any(|(_, call)| match call {
+                rpc::Call::MethodCall(call) => call.method == "eth_fail",
+                _ => false,
+            });
+            let answer = if failed {
+                Err(Error::Unreachable)
+            } else {
+                Ok(requests.iter().map(|(id, _)| Ok(rpc::Value::from(*id))).collect())
+            };
+            future::ready(answer).boxed()
+        }
+    }
+
+    fn queue(batch: &Batch<Chunked>, methods: &[&str]) -> Vec<SingleResult> {
+        methods
+            .iter()
+            .enumerate()
+            .map(|(idx, method)| {
+                let (_, call) = batch.prepare(method, vec![]);
+                batch.send(idx, call)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn should_split_batch_by_count() {
+        let inner = Chunked::default();
+        let mut batch = Batch::new(inner.clone());
+        batch.set_max_batch_size(2);
+        let single = queue(&batch, &["eth_blockNumber"; 5]);
+
+        let results = block_on(batch.submit_batch()).unwrap();
+        assert_eq!(*inner.sizes.lock(), vec![2, 2, 1]);
+        assert_eq!(results, (0..5).map(|id| Ok(rpc::Value::from(id))).collect::<Vec<_>>());
+        assert_eq!(block_on(single.into_iter().last().unwrap()), Ok(rpc::Value::from(4)));
+    }
+
+    #[test]
+    fn should_split_batch_by_response_size() {
+        let inner = Chunked::default();
+        let mut batch = Batch::new(inner.clone());
+        let mut policy = ResponseSizePolicy::new(1_000, 2_000);
+        policy.set_method("eth_getLogs", 2_000);
+        batch.set_response_size_policy(policy);
+        batch.set_concurrent(true);
+        queue(&batch, &["eth_chainId", "eth_chainId", "eth_getLogs", "eth_chainId"]);
+
+        block_on(batch.submit_batch()).unwrap();
+        assert_eq!(*inner.sizes.lock(), vec![2, 1, 1]);
+    }
+
+    #[test]
+    fn should_report_failed_chunks_per_request() {
+        let inner = Chunked::default();
+        let mut batch = Batch::new(inner);
+        batch.set_max_batch_size(2);
+        let single = queue(&batch, &["eth_blockNumber", "eth_fail", "eth_blockNumber"]);
+
+        let results = block_on(batch.submit_batch()).unwrap();
+        assert_eq!(results, vec![Err(Error::Unreachable), Err(Error::Unreachable), Ok(rpc::Value::from(2))]);
+        let single = single.into_iter().map(block_on).collect::<Vec<_>>();
+        assert_eq!(single, results);
+
+        queue(&batch, &["eth_fail"]);
+        assert_eq!(block_on(batch.submit_batch()), Err(Error::Unreachable));
+    }
+}
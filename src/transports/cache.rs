@@ -0,0 +1,446 @@
+//! Response cache middleware
+//!
+//! Results which cannot change any more are kept in canister memory and served without an
+//! outcall. Use it as a middleware layer:
+//!
+//! ```ignore
+//! let transport = Layered::new(ICHttp::new(URL, None)?, Cache::new("mainnet"));
+//! ```
+//!
+//! The cache lives in a `thread_local`, shared by all transports of the canister. Save
+//! [cache_snapshot] in `pre_upgrade` and [restore_cache] in `post_upgrade` to keep it across
+//! upgrades.
+
+use crate::{error, rpc, transports::middleware::Middleware, RequestId};
+use candid::CandidType;
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+/// Size of the cache when not set otherwise, in bytes of keys and values.
+const DEFAULT_CAPACITY: usize = 1_000_000;
+/// Nanoseconds of a slot (12s), volatile results are kept this long by default.
+const SLOT: u64 = 12_000_000_000;
+
+/// Default lifetimes of volatile methods, in nanoseconds.
+const DEFAULT_TTLS: &[(&str, u64)] = &[
+    ("eth_blockNumber", SLOT / 2),
+    ("eth_gasPrice", SLOT),
+    ("eth_maxPriorityFeePerGas", SLOT),
+    ("eth_feeHistory", SLOT),
+];
+
+thread_local! {
+    static STORE: RefCell<Store> = RefCell::new(Store::new(DEFAULT_CAPACITY));
+}
+
+/// Cached result, as kept in stable memory.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq)]
+pub struct CachedEntry {
+    /// Namespace, method and params of the call.
+    pub key: String,
+    /// JSON of the result.
+    pub value: String,
+    /// IC time after which the entry is dropped, in nanoseconds, `None` if it never expires.
+    pub expires: Option<u64>,
+}
+
+/// Cache content and finalized block of each namespace, as kept in stable memory.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct CacheSnapshot {
+    pub entries: Vec<CachedEntry>,
+    pub finalized: Vec<(String, u64)>,
+}
+
+struct Stored {
+    value: String,
+    expires: Option<u64>,
+    used: u64,
+}
+
+struct Store {
+    entries: HashMap<String, Stored>,
+    // least recently used first
+    usage: BTreeMap<u64, String>,
+    finalized: HashMap<String, u64>,
+    counter: u64,
+    bytes: usize,
+    capacity: usize,
+}
+
+impl Store {
+    fn new(capacity: usize) -> Self {
+        Store {
+            entries: HashMap::new(),
+            usage: BTreeMap::new(),
+            finalized: HashMap::new(),
+            counter: 0,
+            bytes: 0,
+            capacity,
+        }
+    }
+
+    fn touch(&mut self, key: &str) {
+        self.counter += 1;
+        if let Some(stored) = self.entries.get_mut(key) {
+            self.usage.remove(&stored.used);
+            stored.used = self.counter;
+            self.usage.insert(self.counter, key.to_string());
+        }
+    }
+
+    fn get(&mut self, key: &str, now: u64) -> Option<String> {
+        let expired = self.entries.get(key)?.expires.map(|e| e <= now).unwrap_or(false);
+        if expired {
+            self.remove(key);
+            return None;
+        }
+        self.touch(key);
+        self.entries.get(key).map(|stored| stored.value.clone())
+    }
+
+    fn insert(&mut self, key: String, value: String, expires: Option<u64>) {
+        let size = key.len() + value.len();
+        if size > self.capacity {
+            return;
+        }
+        self.remove(&key);
+        while self.bytes + size > self.capacity {
+            match self.usage.values().next().cloned() {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+        self.counter += 1;
+        self.bytes += size;
+        self.usage.insert(self.counter, key.clone());
+        self.entries.insert(
+            key,
+            Stored {
+                value,
+                expires,
+                used: self.counter,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(stored) = self.entries.remove(key) {
+            self.usage.remove(&stored.used);
+            self.bytes -= key.len() + stored.value.len();
+        }
+    }
+
+    fn set_capacity(&mut self, capacity: usize) {
+        self.capacity = capacity;
+        while self.bytes > self.capacity {
+            match self.usage.values().next().cloned() {
+                Some(oldest) => self.remove(&oldest),
+                None => break,
+            }
+        }
+    }
+}
+
+/// Set the size of the cache, in bytes of keys and values. Least recently used entries are
+/// dropped first.
+pub fn set_cache_capacity(bytes: usize) {
+    STORE.with(|s| s.borrow_mut().set_capacity(bytes));
+}
+
+/// Drops every cached result.
+pub fn clear_cache() {
+    STORE.with(|s| {
+        let capacity = s.borrow().capacity;
+        *s.borrow_mut() = Store::new(capacity);
+    });
+}
+
+/// Copies the cache, e.g. to save it to stable memory before an upgrade.
+pub fn cache_snapshot() -> CacheSnapshot {
+    STORE.with(|s| {
+        let store = s.borrow();
+        CacheSnapshot {
+            // oldest first, so restoring keeps the usage order
+            entries: store
+                .usage
+                .values()
+                .map(|key| {
+                    let stored = &store.entries[key];
+                    CachedEntry {
+                        key: key.clone(),
+                        value: stored.value.clone(),
+                        expires: stored.expires,
+                    }
+                })
+                .collect(),
+            finalized: store.finalized.iter().map(|(ns, n)| (ns.clone(), *n)).collect(),
+        }
+    })
+}
+
+/// Replaces the cache with a snapshot, e.g. loaded from stable memory after an upgrade.
+pub fn restore_cache(snapshot: CacheSnapshot) {
+    STORE.with(|s| {
+        let mut store = s.borrow_mut();
+        *store = Store::new(store.capacity);
+        for entry in snapshot.entries {
+            store.insert(entry.key, entry.value, entry.expires);
+        }
+        store.finalized = snapshot.finalized.into_iter().collect();
+    });
+}
+
+/// Caching middleware.
+///
+/// Caches forever:
+/// - `eth_chainId` and `net_version`,
+/// - blocks by hash,
+/// - transactions and receipts included in a finalized block,
+/// - `eth_getCode`, `eth_getBalance`, `eth_call` and similar at a block hash, or at a block
+///   number not above the finalized block.
+///
+/// Other methods are cached for their TTL, if they have one. The finalized block is learned
+/// from `eth_getBlockByNumber("finalized", ..)` results passing through any cache of the same
+/// namespace, or set with [Cache::set_finalized_block].
+#[derive(Debug, Clone)]
+pub struct Cache {
+    namespace: String,
+    ttls: Arc<HashMap<String, u64>>,
+    clock: fn() -> u64,
+}
+
+impl Cache {
+    /// Create new cache for the calls of a chain, e.g. `"mainnet"`. Caches of the same namespace
+    /// share their entries.
+    pub fn new(namespace: &str) -> Self {
+        Cache {
+            namespace: namespace.to_string(),
+            ttls: Arc::new(DEFAULT_TTLS.iter().map(|(m, ttl)| (m.to_string(), *ttl)).collect()),
+            clock: ic_cdk::api::time,
+        }
+    }
+
+    /// Set how long results of a method are kept, in nanoseconds. A zero TTL disables caching.
+    pub fn set_ttl(&mut self, method: &str, ttl: u64) {
+        Arc::make_mut(&mut self.ttls).insert(method.to_string(), ttl);
+    }
+
+    /// Set the clock of the cache, returning the time in nanoseconds. Defaults to the IC time.
+    pub fn set_clock(&mut self, clock: fn() -> u64) {
+        self.clock = clock;
+    }
+
+    /// Set the number of the latest finalized block.
+    pub fn set_finalized_block(&self, number: u64) {
+        STORE.with(|s| {
+            let mut store = s.borrow_mut();
+            let finalized = store.finalized.entry(self.namespace.clone()).or_default();
+            *finalized = number.max(*finalized);
+        });
+    }
+
+    fn finalized_block(&self) -> Option<u64> {
+        STORE.with(|s| s.borrow().finalized.get(&self.namespace).copied())
+    }
+
+    fn key(&self, call: &rpc::Call) -> Option<(String, String, Vec<rpc::Value>)> {
+        let call = match call {
+            rpc::Call::MethodCall(call) => call,
+            _ => return None,
+        };
+        let params = match &call.params {
+            rpc::Params::Array(params) => params.clone(),
+            rpc::Params::None => vec![],
+            rpc::Params::Map(_) => return None,
+        };
+        let key = format!("{}/{}/{}", self.namespace, call.method, rpc::Value::Array(params.clone()));
+        Some((key, call.method.clone(), params))
+    }
+
+    /// Whether a block parameter names a block which cannot change any more.
+    fn is_final(&self, block: Option<&rpc::Value>) -> bool {
+        match block {
+            Some(rpc::Value::Object(block)) => block.contains_key("blockHash"),
+            Some(rpc::Value::String(block)) if block.starts_with("0x") => {
+                match (quantity(block), self.finalized_block()) {
+                    (Some(number), Some(finalized)) => number <= finalized,
+                    _ => false,
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// `Some(expires)` if the result can be cached, `expires` being `None` for results which
+    /// never change.
+    fn lifetime(&self, method: &str, params: &[rpc::Value], result: &rpc::Value, now: u64) -> Option<Option<u64>> {
+        let forever = match method {
+            "eth_chainId" | "net_version" => true,
+            _ if result.is_null() => return None,
+            "eth_getBlockByHash" | "eth_getTransactionByBlockHashAndIndex" => true,
+            "eth_getTransactionReceipt" | "eth_getTransactionByHash" => self.is_final(result.get("blockNumber")),
+            "eth_getBlockByNumber" => self.is_final(params.first()),
+            "eth_getCode" | "eth_getBalance" | "eth_getTransactionCount" | "eth_getStorageAt" | "eth_call" => {
+                self.is_final(params.last())
+            }
+            _ => false,
+        };
+        if forever {
+            return Some(None);
+        }
+        match self.ttls.get(method) {
+            Some(ttl) if *ttl > 0 => Some(Some(now.saturating_add(*ttl))),
+            _ => None,
+        }
+    }
+
+    fn observe_finalized(&self, method: &str, params: &[rpc::Value], result: &rpc::Value) {
+        if method == "eth_getBlockByNumber" && params.first().and_then(rpc::Value::as_str) == Some("finalized") {
+            if let Some(number) = result.get("number").and_then(rpc::Value::as_str).and_then(quantity) {
+                self.set_finalized_block(number);
+            }
+        }
+    }
+}
+
+fn quantity(value: &str) -> Option<u64> {
+    u64::from_str_radix(value.trim_start_matches("0x"), 16).ok()
+}
+
+impl Middleware for Cache {
+    fn intercept(&self, _id: RequestId, call: &rpc::Call) -> Option<error::Result<rpc::Value>> {
+        let (key, _, _) = self.key(call)?;
+        let now = (self.clock)();
+        let value = STORE.with(|s| s.borrow_mut().get(&key, now))?;
+        serde_json::from_str(&value).ok().map(Ok)
+    }
+
+    fn on_response(
+        &self,
+        _id: RequestId,
+        call: &rpc::Call,
+        result: error::Result<rpc::Value>,
+    ) -> error::Result<rpc::Value> {
+        let value = match (&result, self.key(call)) {
+            (Ok(value), Some((key, method, params))) => {
+                self.observe_finalized(&method, &params, value);
+                let now = (self.clock)();
+                // results served from the cache keep their expiry
+                if STORE.with(|s| s.borrow().entries.contains_key(&key)) {
+                    return result;
+                }
+                self.lifetime(&method, &params, value, now).map(|expires| (key, value.to_string(), expires))
+            }
+            _ => None,
+        };
+        if let Some((key, value, expires)) = value {
+            STORE.with(|s| s.borrow_mut().insert(key, value, expires));
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        transports::{middleware::Layered, test::TestTransport},
+        Transport,
+    };
+    use futures::executor::block_on;
+    use serde_json::json;
+
+    thread_local! {
+        static NOW: RefCell<u64> = RefCell::new(0);
+    }
+
+    fn now() -> u64 {
+        NOW.with(|n| *n.borrow())
+    }
+
+    fn advance(ns: u64) {
+        NOW.with(|n| *n.borrow_mut() += ns);
+    }
+
+    fn cache() -> Cache {
+        let mut cache = Cache::new("test");
+        cache.set_clock(now);
+        cache
+    }
+
+    #[test]
+    fn should_serve_immutable_results_from_cache() {
+        let mut inner = TestTransport::default();
+        inner.add_response(json!("0x1"));
+        let transport = Layered::new(inner.clone(), cache());
+
+        assert_eq!(block_on(transport.execute("eth_chainId", vec![])), Ok(json!("0x1")));
+        assert_eq!(block_on(transport.execute("eth_chainId", vec![])), Ok(json!("0x1")));
+        inner.assert_request("eth_chainId", &[]);
+        inner.assert_no_more_requests();
+    }
+
+    #[test]
+    fn should_expire_volatile_results() {
+        let mut inner = TestTransport::default();
+        inner.add_response(json!("0x10"));
+        inner.add_response(json!("0x11"));
+        let transport = Layered::new(inner, cache());
+
+        assert_eq!(block_on(transport.execute("eth_gasPrice", vec![])), Ok(json!("0x10")));
+        advance(SLOT / 2);
+        assert_eq!(block_on(transport.execute("eth_gasPrice", vec![])), Ok(json!("0x10")));
+        advance(SLOT / 2);
+        assert_eq!(block_on(transport.execute("eth_gasPrice", vec![])), Ok(json!("0x11")));
+    }
+
+    #[test]
+    fn should_cache_receipts_of_finalized_blocks_only() {
+        let cache = cache();
+        let receipt = json!({"blockNumber": "0x10", "status": "0x1"});
+        cache.set_finalized_block(0xf);
+        assert_eq!(cache.lifetime("eth_getTransactionReceipt", &[], &receipt, 0), None);
+        cache.set_finalized_block(0x10);
+        assert_eq!(cache.lifetime("eth_getTransactionReceipt", &[], &receipt, 0), Some(None));
+        assert_eq!(cache.lifetime("eth_getTransactionReceipt", &[], &rpc::Value::Null, 0), None);
+    }
+
+    #[test]
+    fn should_learn_finalized_block() {
+        let cache = cache();
+        let params = [json!("finalized"), json!(false)];
+        cache.observe_finalized("eth_getBlockByNumber", &params, &json!({"number": "0x20"}));
+        assert_eq!(cache.finalized_block(), Some(0x20));
+        assert_eq!(cache.lifetime("eth_getCode", &[json!("0x00"), json!("0x20")], &json!("0x"), 0), Some(None));
+        assert_eq!(cache.lifetime("eth_getCode", &[json!("0x00"), json!("latest")], &json!("0x"), 0), None);
+        let at_hash = json!({"blockHash": "0xab"});
+        assert_eq!(cache.lifetime("eth_call", &[json!({}), at_hash], &json!("0x"), 0), Some(None));
+    }
+
+    #[test]
+    fn should_evict_least_recently_used() {
+        let mut store = Store::new(20);
+        store.insert("a".into(), "123456789".into(), None);
+        store.insert("b".into(), "123456789".into(), None);
+        assert!(store.get("a", 0).is_some());
+        store.insert("c".into(), "123456789".into(), None);
+        assert!(store.get("b", 0).is_none());
+        assert!(store.get("a", 0).is_some());
+        assert!(store.get("c", 0).is_some());
+    }
+
+    #[test]
+    fn should_restore_snapshot() {
+        STORE.with(|s| s.borrow_mut().insert("test/eth_chainId/[]".into(), "\"0x1\"".into(), None));
+        cache().set_finalized_block(7);
+        let snapshot = cache_snapshot();
+        clear_cache();
+        assert_eq!(cache_snapshot(), CacheSnapshot::default());
+        restore_cache(snapshot.clone());
+        assert_eq!(cache_snapshot(), snapshot);
+    }
+}
@@ -0,0 +1,219 @@
+//! Multi-provider consensus transport
+
+use crate::{
+    error::{self, Error, TransportError},
+    helpers, rpc,
+    transports::ICHttp,
+    BatchTransport, RequestId, Transport,
+};
+use futures::future::{join_all, BoxFuture, FutureExt};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Transport sending every call to several providers.
+///
+/// A result is only accepted when at least `quorum` providers returned the same answer,
+/// e.g. 2-of-3. Otherwise the call fails with `Error::Inconsistent` which lists the answer
+/// of each provider, in the order the providers were given.
+#[derive(Debug, Clone)]
+pub struct Consensus<T> {
+    transports: Vec<T>,
+    quorum: usize,
+    id: Arc<AtomicUsize>,
+}
+
+impl<T: Transport> Consensus<T> {
+    /// Create new consensus transport over given providers.
+    ///
+    /// Fails if `quorum` is zero or larger than the number of providers.
+    pub fn new(transports: Vec<T>, quorum: usize) -> error::Result<Self> {
+        if quorum == 0 || quorum > transports.len() {
+            return Err(Error::Transport(TransportError::Message(format!(
+                "quorum {} is out of range for {} providers",
+                quorum,
+                transports.len()
+            ))));
+        }
+        Ok(Self {
+            transports,
+            quorum,
+            id: Default::default(),
+        })
+    }
+
+    /// Number of providers which have to agree on a result.
+    pub fn quorum(&self) -> usize {
+        self.quorum
+    }
+
+    /// Borrows the underlying providers.
+    pub fn transports(&self) -> &[T] {
+        &self.transports
+    }
+
+    fn next_id(&self) -> RequestId {
+        self.id.fetch_add(1, Ordering::AcqRel)
+    }
+}
+
+impl Consensus<ICHttp> {
+    /// Create new consensus transport over `ICHttp` endpoints, one for each URL.
+    pub fn from_urls(urls: &[&str], quorum: usize, max_resp: Option<u64>) -> error::Result<Self> {
+        let transports = urls
+            .iter()
+            .map(|url| ICHttp::new(url, max_resp))
+            .collect::<error::Result<Vec<_>>>()?;
+        Self::new(transports, quorum)
+    }
+}
+
+impl<T> Transport for Consensus<T>
+where
+    T: Transport,
+    T::Out: 'static + Send,
+{
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.next_id();
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let answers = self
+            .transports
+            .iter()
+            .map(|transport| transport.send(id, request.clone()))
+            .collect::<Vec<_>>();
+        let quorum = self.quorum;
+        join_all(answers).map(move |answers| tally(answers, quorum)).boxed()
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        for transport in self.transports.iter_mut() {
+            transport.set_max_response_bytes(v);
+        }
+    }
+}
+
+impl<T> BatchTransport for Consensus<T>
+where
+    T: BatchTransport,
+    T::Out: 'static + Send,
+    T::Batch: 'static + Send,
+{
+    type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let requests = requests.into_iter().collect::<Vec<_>>();
+        let len = requests.len();
+        let batches = self
+            .transports
+            .iter()
+            .map(|transport| transport.send_batch(requests.clone()))
+            .collect::<Vec<_>>();
+        let quorum = self.quorum;
+        join_all(batches)
+            .map(move |batches| tally_batch(batches, len, quorum))
+            .boxed()
+    }
+}
+
+/// Two answers agree if they are equal values or equal JSON-RPC errors.
+fn agree(a: &error::Result<rpc::Value>, b: &error::Result<rpc::Value>) -> bool {
+    match (a, b) {
+        (Ok(a), Ok(b)) => a == b,
+        (Err(Error::Rpc(a)), Err(Error::Rpc(b))) => a == b,
+        _ => false,
+    }
+}
+
+fn tally(answers: Vec<error::Result<rpc::Value>>, quorum: usize) -> error::Result<rpc::Value> {
+    let agreed = answers
+        .iter()
+        .find(|answer| answers.iter().filter(|other| agree(answer, other)).count() >= quorum)
+        .cloned();
+    match agreed {
+        Some(answer) => answer,
+        None => Err(Error::Inconsistent(answers)),
+    }
+}
+
+fn tally_batch(
+    batches: Vec<error::Result<Vec<error::Result<rpc::Value>>>>,
+    len: usize,
+    quorum: usize,
+) -> error::Result<Vec<error::Result<rpc::Value>>> {
+    if batches.iter().all(|batch| batch.is_err()) {
+        let errors = batches.into_iter().map(|batch| batch.map(|_| rpc::Value::Null)).collect();
+        return Err(Error::Inconsistent(errors));
+    }
+    Ok((0..len)
+        .map(|idx| {
+            let answers = batches
+                .iter()
+                .map(|batch| match batch {
+                    Ok(results) => results
+                        .get(idx)
+                        .cloned()
+                        .unwrap_or_else(|| Err(Error::InvalidResponse("unexpected number of responses".into()))),
+                    Err(err) => Err(err.clone()),
+                })
+                .collect();
+            tally(answers, quorum)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rpc_error(code: i64) -> Error {
+        Error::Rpc(rpc::Error::new(rpc::ErrorCode::ServerError(code)))
+    }
+
+    #[test]
+    fn should_accept_answer_with_quorum() {
+        let answers = vec![Ok(1.into()), Ok(2.into()), Ok(1.into())];
+        assert_eq!(tally(answers, 2), Ok(1.into()));
+    }
+
+    #[test]
+    fn should_accept_agreeing_rpc_errors() {
+        let answers = vec![Err(rpc_error(3)), Ok(2.into()), Err(rpc_error(3))];
+        assert_eq!(tally(answers, 2), Err(rpc_error(3)));
+    }
+
+    #[test]
+    fn should_list_answers_without_quorum() {
+        let answers = vec![Ok(1.into()), Err(Error::Unreachable), Ok(2.into())];
+        assert_eq!(tally(answers.clone(), 2), Err(Error::Inconsistent(answers)));
+    }
+
+    #[test]
+    fn should_tally_batches_per_request() {
+        let batches = vec![
+            Ok(vec![Ok(1.into()), Ok(5.into())]),
+            Err(Error::Unreachable),
+            Ok(vec![Ok(1.into()), Ok(6.into())]),
+        ];
+        let results = tally_batch(batches, 2, 2).unwrap();
+        assert_eq!(results[0], Ok(1.into()));
+        assert_eq!(
+            results[1],
+            Err(Error::Inconsistent(vec![Ok(5.into()), Err(Error::Unreachable), Ok(6.into())]))
+        );
+    }
+
+    #[test]
+    fn should_reject_invalid_quorum() {
+        assert!(Consensus::new(vec![crate::transports::test::TestTransport::default()], 2).is_err());
+    }
+}
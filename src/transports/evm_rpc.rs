@@ -0,0 +1,554 @@
+//! EVM RPC canister transport
+//!
+//! Speaks the Candid interface of the IC's EVM RPC canister: the generic `request` method for
+//! any JSON-RPC call, and the typed multi-provider methods where they exist.
+
+use crate::{
+    error::{Error, Result, TransportError},
+    helpers, rpc,
+    transforms::{raw_transaction_hash, SEND_RAW_TRANSACTION},
+    BatchTransport, RequestId, Transport,
+};
+use candid::{
+    utils::{ArgumentDecoder, ArgumentEncoder},
+    CandidType, Nat, Principal,
+};
+use futures::future::join_all;
+#[cfg(not(feature = "wasm"))]
+use futures::future::BoxFuture;
+#[cfg(feature = "wasm")]
+use futures::future::LocalBoxFuture as BoxFuture;
+use ic_cdk::api::{
+    call::{call_raw128, CallResult, RejectionCode},
+    management_canister::http_request::HttpHeader,
+};
+use jsonrpc_core::types::{Call, Output, Params, Request, Value};
+use serde::Deserialize;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Cycles attached to typed calls, which have no cost query. The canister refunds what it does
+/// not use.
+const DEFAULT_CYCLES: u128 = 10_000_000_000;
+/// Response size limit of generic requests when not set otherwise.
+const DEFAULT_MAX_RESPONSE_BYTES: u64 = 500_000;
+
+/// Calls a method of the canister with Candid encoded arguments and cycles, returning the encoded reply.
+pub type CanisterCall = fn(Principal, String, Vec<u8>, u128) -> BoxFuture<'static, CallResult<Vec<u8>>>;
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EthMainnetService {
+    Alchemy,
+    Ankr,
+    BlockPi,
+    PublicNode,
+    Cloudflare,
+    Llama,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EthSepoliaService {
+    Alchemy,
+    Ankr,
+    BlockPi,
+    PublicNode,
+    Sepolia,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum L2MainnetService {
+    Alchemy,
+    Ankr,
+    BlockPi,
+    PublicNode,
+    Llama,
+}
+
+/// Custom provider, its URL and headers, e.g. an API key header.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RpcApi {
+    pub url: String,
+    pub headers: Option<Vec<HttpHeader>>,
+}
+
+/// Single provider of a generic `request`.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum RpcService {
+    Chain(u64),
+    Provider(u64),
+    Custom(RpcApi),
+    EthMainnet(EthMainnetService),
+    EthSepolia(EthSepoliaService),
+    ArbitrumOne(L2MainnetService),
+    BaseMainnet(L2MainnetService),
+    OptimismMainnet(L2MainnetService),
+}
+
+/// Providers of a typed call, `None` picks the canister defaults of the chain.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum RpcServices {
+    Custom {
+        #[serde(rename = "chainId")]
+        chain_id: u64,
+        services: Vec<RpcApi>,
+    },
+    EthMainnet(Option<Vec<EthMainnetService>>),
+    EthSepolia(Option<Vec<EthSepoliaService>>),
+    ArbitrumOne(Option<Vec<L2MainnetService>>),
+    BaseMainnet(Option<Vec<L2MainnetService>>),
+    OptimismMainnet(Option<Vec<L2MainnetService>>),
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct RpcConfig {
+    #[serde(rename = "responseSizeEstimate")]
+    pub response_size_estimate: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum BlockTag {
+    Earliest,
+    Safe,
+    Finalized,
+    Latest,
+    Number(Nat),
+    Pending,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct GetTransactionCountArgs {
+    pub address: String,
+    pub block: BlockTag,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct JsonRpcError {
+    pub code: i64,
+    pub message: String,
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ProviderError {
+    TooFewCycles { expected: Nat, received: Nat },
+    MissingRequiredProvider,
+    ProviderNotFound,
+    NoPermission,
+    InvalidRpcConfig(String),
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum HttpOutcallError {
+    IcError {
+        code: RejectionCode,
+        message: String,
+    },
+    InvalidHttpJsonRpcResponse {
+        status: u16,
+        body: String,
+        #[serde(rename = "parsingError")]
+        parsing_error: Option<String>,
+    },
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    Custom(String),
+    InvalidHex(String),
+}
+
+/// Error of the EVM RPC canister.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum RpcError {
+    JsonRpcError(JsonRpcError),
+    ProviderError(ProviderError),
+    ValidationError(ValidationError),
+    HttpOutcallError(HttpOutcallError),
+}
+
+impl From<RpcError> for Error {
+    fn from(err: RpcError) -> Self {
+        match err {
+            RpcError::JsonRpcError(err) => Error::Rpc(rpc::Error {
+                code: rpc::ErrorCode::from(err.code),
+                message: err.message,
+                data: None,
+            }),
+            RpcError::HttpOutcallError(HttpOutcallError::IcError { code, message }) => {
+                Error::Transport(TransportError::Rejected(code, message))
+            }
+            RpcError::HttpOutcallError(HttpOutcallError::InvalidHttpJsonRpcResponse { status, body, .. }) => {
+                match status {
+                    429 => Error::Transport(TransportError::RateLimited(body)),
+                    200..=299 => Error::InvalidResponse(body),
+                    status => Error::Transport(TransportError::Code(status, body)),
+                }
+            }
+            err => Error::Transport(TransportError::Message(format!("{:?}", err))),
+        }
+    }
+}
+
+pub type RequestResult = std::result::Result<String, RpcError>;
+pub type RequestCostResult = std::result::Result<Nat, RpcError>;
+pub type GetTransactionCountResult = std::result::Result<Nat, RpcError>;
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum MultiGetTransactionCountResult {
+    Consistent(GetTransactionCountResult),
+    Inconsistent(Vec<(RpcService, GetTransactionCountResult)>),
+}
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum SendRawTransactionStatus {
+    Ok(Option<String>),
+    NonceTooLow,
+    NonceTooHigh,
+    InsufficientFunds,
+}
+
+pub type SendRawTransactionResult = std::result::Result<SendRawTransactionStatus, RpcError>;
+
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub enum MultiSendRawTransactionResult {
+    Consistent(SendRawTransactionResult),
+    Inconsistent(Vec<(RpcService, SendRawTransactionResult)>),
+}
+
+/// Transport calling the EVM RPC canister.
+///
+/// Every call goes through the generic `request` method to a single provider, with the cycles
+/// quoted by `requestCost`. Once [EvmRpc::set_services] is called, `eth_getTransactionCount`
+/// and `eth_sendRawTransaction` use the typed methods instead, which ask several providers and
+/// fail with `Error::InconsistentServices` when they disagree.
+#[derive(Clone, Debug)]
+pub struct EvmRpc {
+    canister: Principal,
+    service: RpcService,
+    services: Option<(RpcServices, Option<RpcConfig>)>,
+    max_response_bytes: u64,
+    cycles: u128,
+    call: CanisterCall,
+    id: Arc<AtomicUsize>,
+}
+
+impl EvmRpc {
+    /// Create new transport sending generic requests to `service` through the `canister`.
+    pub fn new(canister: Principal, service: RpcService) -> Self {
+        EvmRpc {
+            canister,
+            service,
+            services: None,
+            max_response_bytes: DEFAULT_MAX_RESPONSE_BYTES,
+            cycles: DEFAULT_CYCLES,
+            call: |canister, method, args, cycles| {
+                Box::pin(async move { call_raw128(canister, &method, args, cycles).await })
+            },
+            id: Default::default(),
+        }
+    }
+
+    /// Use the typed methods with the given providers where the canister has them.
+    pub fn set_services(&mut self, services: RpcServices, config: Option<RpcConfig>) {
+        self.services = Some((services, config));
+    }
+
+    /// Set the cycles attached to typed calls.
+    pub fn set_cycles(&mut self, cycles: u128) {
+        self.cycles = cycles;
+    }
+
+    /// Set how the canister is called, an inter-canister call by default.
+    pub fn set_call(&mut self, call: CanisterCall) {
+        self.call = call;
+    }
+
+    /// The EVM RPC canister.
+    pub fn canister(&self) -> Principal {
+        self.canister
+    }
+
+    fn next_id(&self) -> RequestId {
+        self.id.fetch_add(1, Ordering::AcqRel)
+    }
+
+    async fn call<A, R>(&self, method: &str, args: A, cycles: u128) -> Result<R>
+    where
+        A: ArgumentEncoder,
+        R: for<'a> ArgumentDecoder<'a>,
+    {
+        let args = candid::encode_args(args).map_err(|err| Error::Decoder(err.to_string()))?;
+        let reply = (self.call)(self.canister, method.to_string(), args, cycles)
+            .await
+            .map_err(rejected)?;
+        candid::decode_args(&reply).map_err(|err| Error::Decoder(format!("failed to decode {} reply: {}", method, err)))
+    }
+
+    async fn execute(&self, call: Call) -> Result<Value> {
+        if let (Some((services, config)), Call::MethodCall(method_call)) = (&self.services, &call) {
+            let params = params(&method_call.params);
+            match method_call.method.as_str() {
+                "eth_getTransactionCount" => return self.transaction_count(services, config, &params).await,
+                SEND_RAW_TRANSACTION => {
+                    let hash = raw_transaction_hash(method_call);
+                    return self.send_raw_transaction(services, config, &params, hash).await;
+                }
+                _ => {}
+            }
+        }
+        self.request(&Request::Single(call)).await
+    }
+
+    async fn request(&self, request: &Request) -> Result<Value> {
+        let json = serde_json::to_string(request)?;
+        let args = (self.service.clone(), json, self.max_response_bytes);
+        let (cost,): (RequestCostResult,) = self.call("requestCost", args.clone(), 0).await?;
+        let cycles = u128::try_from(&cost?.0).unwrap_or(u128::MAX);
+        let (reply,): (RequestResult,) = self.call("request", args, cycles).await?;
+        let body = reply?;
+        let output: Output = helpers::arbitrary_precision_deserialize_workaround(body.as_bytes())
+            .map_err(|err| Error::InvalidResponse(format!("failed to deserialize response: {}: {}", err, body)))?;
+        helpers::to_result_from_output(output)
+    }
+
+    async fn transaction_count(
+        &self,
+        services: &RpcServices,
+        config: &Option<RpcConfig>,
+        params: &[Value],
+    ) -> Result<Value> {
+        let args = GetTransactionCountArgs {
+            address: param_str(params, 0)?.to_string(),
+            block: block_tag(params.get(1))?,
+        };
+        let (reply,): (MultiGetTransactionCountResult,) = self
+            .call(
+                "eth_getTransactionCount",
+                (services.clone(), config.clone(), args),
+                self.cycles,
+            )
+            .await?;
+        match reply {
+            MultiGetTransactionCountResult::Consistent(count) => count_value(count),
+            MultiGetTransactionCountResult::Inconsistent(counts) => Err(Error::InconsistentServices(
+                counts
+                    .into_iter()
+                    .map(|(service, count)| (service, count_value(count)))
+                    .collect(),
+            )),
+        }
+    }
+
+    async fn send_raw_transaction(
+        &self,
+        services: &RpcServices,
+        config: &Option<RpcConfig>,
+        params: &[Value],
+        hash: Option<crate::types::H256>,
+    ) -> Result<Value> {
+        let raw = param_str(params, 0)?.to_string();
+        let (reply,): (MultiSendRawTransactionResult,) = self
+            .call(
+                SEND_RAW_TRANSACTION,
+                (services.clone(), config.clone(), raw),
+                self.cycles,
+            )
+            .await?;
+        let hash = hash.map(serde_json::to_value).transpose()?;
+        match reply {
+            MultiSendRawTransactionResult::Consistent(status) => status_value(status, &hash),
+            MultiSendRawTransactionResult::Inconsistent(statuses) => Err(Error::InconsistentServices(
+                statuses
+                    .into_iter()
+                    .map(|(service, status)| (service, status_value(status, &hash)))
+                    .collect(),
+            )),
+        }
+    }
+}
+
+fn rejected((code, message): (RejectionCode, String)) -> Error {
+    Error::Transport(TransportError::Rejected(code, message))
+}
+
+fn params(params: &Params) -> Vec<Value> {
+    match params {
+        Params::Array(params) => params.clone(),
+        Params::Map(_) | Params::None => vec![],
+    }
+}
+
+fn param_str(params: &[Value], idx: usize) -> Result<&str> {
+    params
+        .get(idx)
+        .and_then(Value::as_str)
+        .ok_or_else(|| Error::Decoder(format!("expected a string parameter at {}", idx)))
+}
+
+fn block_tag(param: Option<&Value>) -> Result<BlockTag> {
+    let tag = match param.and_then(Value::as_str) {
+        None | Some("latest") => BlockTag::Latest,
+        Some("earliest") => BlockTag::Earliest,
+        Some("pending") => BlockTag::Pending,
+        Some("safe") => BlockTag::Safe,
+        Some("finalized") => BlockTag::Finalized,
+        Some(number) => {
+            let number = u64::from_str_radix(number.trim_start_matches("0x"), 16)
+                .map_err(|err| Error::Decoder(format!("invalid block number {}: {}", number, err)))?;
+            BlockTag::Number(Nat::from(number))
+        }
+    };
+    Ok(tag)
+}
+
+fn count_value(count: GetTransactionCountResult) -> Result<Value> {
+    Ok(Value::String(format!("0x{:x}", count?.0)))
+}
+
+fn status_value(status: SendRawTransactionResult, hash: &Option<Value>) -> Result<Value> {
+    let server_error = |message: &str| {
+        let mut err = rpc::Error::new(rpc::ErrorCode::ServerError(-32000));
+        err.message = message.to_string();
+        Error::Rpc(err)
+    };
+    match status? {
+        SendRawTransactionStatus::Ok(Some(hash)) => Ok(Value::String(hash)),
+        SendRawTransactionStatus::Ok(None) => {
+            hash.clone().ok_or_else(|| Error::Decoder("invalid raw transaction".into()))
+        }
+        SendRawTransactionStatus::NonceTooLow => Err(server_error("nonce too low")),
+        SendRawTransactionStatus::NonceTooHigh => Err(server_error("nonce too high")),
+        SendRawTransactionStatus::InsufficientFunds => Err(server_error("insufficient funds")),
+    }
+}
+
+impl Transport for EvmRpc {
+    type Out = BoxFuture<'static, Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.next_id();
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, _id: RequestId, call: Call) -> Self::Out {
+        let evm = self.clone();
+        Box::pin(async move { evm.execute(call).await })
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.max_response_bytes = v;
+    }
+}
+
+impl BatchTransport for EvmRpc {
+    type Batch = BoxFuture<'static, Result<Vec<Result<Value>>>>;
+
+    /// Sends the calls of the batch one by one, concurrently.
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let answers = requests
+            .into_iter()
+            .map(|(id, call)| self.send(id, call))
+            .collect::<Vec<_>>();
+        Box::pin(async move { Ok(join_all(answers).await) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_map_canister_errors() {
+        let json_rpc = RpcError::JsonRpcError(JsonRpcError {
+            code: -32000,
+            message: "execution reverted".into(),
+        });
+        match Error::from(json_rpc) {
+            Error::Rpc(err) => assert_eq!(err.message, "execution reverted"),
+            err => panic!("unexpected {:?}", err),
+        }
+        let limited = RpcError::HttpOutcallError(HttpOutcallError::InvalidHttpJsonRpcResponse {
+            status: 429,
+            body: "slow down".into(),
+            parsing_error: None,
+        });
+        assert!(Error::from(limited).is_rate_limited());
+        let ic = RpcError::HttpOutcallError(HttpOutcallError::IcError {
+            code: RejectionCode::SysTransient,
+            message: "timeout".into(),
+        });
+        assert_eq!(
+            Error::from(ic),
+            Error::Transport(TransportError::Rejected(RejectionCode::SysTransient, "timeout".into()))
+        );
+    }
+
+    fn inconsistent_counts(
+        _canister: Principal,
+        method: String,
+        _args: Vec<u8>,
+        _cycles: u128,
+    ) -> BoxFuture<'static, CallResult<Vec<u8>>> {
+        assert_eq!(method, "eth_getTransactionCount");
+        let reply = MultiGetTransactionCountResult::Inconsistent(vec![
+            (RpcService::EthMainnet(EthMainnetService::Ankr), Ok(Nat::from(5u64))),
+            (RpcService::EthMainnet(EthMainnetService::Alchemy), Ok(Nat::from(6u64))),
+        ]);
+        Box::pin(futures::future::ready(Ok(candid::encode_one(reply).unwrap())))
+    }
+
+    #[test]
+    fn should_list_inconsistent_answers() {
+        let mut transport = EvmRpc::new(Principal::anonymous(), RpcService::Chain(1));
+        transport.set_services(RpcServices::EthMainnet(None), None);
+        transport.set_call(inconsistent_counts);
+
+        let params = vec![Value::String("0x0000000000000000000000000000000000000123".into()), "latest".into()];
+        let answer = futures::executor::block_on(transport.execute("eth_getTransactionCount", params));
+
+        assert_eq!(
+            answer,
+            Err(Error::InconsistentServices(vec![
+                (RpcService::EthMainnet(EthMainnetService::Ankr), Ok("0x5".into())),
+                (RpcService::EthMainnet(EthMainnetService::Alchemy), Ok("0x6".into())),
+            ]))
+        );
+    }
+
+    #[test]
+    fn should_parse_block_tags() {
+        assert_eq!(block_tag(None).unwrap(), BlockTag::Latest);
+        assert_eq!(block_tag(Some(&"pending".into())).unwrap(), BlockTag::Pending);
+        assert_eq!(block_tag(Some(&"0x10".into())).unwrap(), BlockTag::Number(Nat::from(16u64)));
+        assert!(block_tag(Some(&"0xzz".into())).is_err());
+    }
+
+    #[test]
+    fn should_map_raw_transaction_status() {
+        let hash = Some(Value::String("0xab".into()));
+        assert_eq!(
+            status_value(Ok(SendRawTransactionStatus::Ok(Some("0xcd".into()))), &hash),
+            Ok("0xcd".into())
+        );
+        assert_eq!(status_value(Ok(SendRawTransactionStatus::Ok(None)), &hash), Ok("0xab".into()));
+        assert!(status_value(Ok(SendRawTransactionStatus::NonceTooLow), &hash).is_err());
+    }
+
+    #[test]
+    fn should_encode_services_with_candid_names() {
+        let services = RpcServices::Custom {
+            chain_id: 1,
+            services: vec![RpcApi {
+                url: "https://rpc".into(),
+                headers: None,
+            }],
+        };
+        let bytes = candid::encode_one(&services).unwrap();
+        assert_eq!(candid::decode_one::<RpcServices>(&bytes).unwrap(), services);
+    }
+}
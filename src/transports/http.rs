@@ -2,7 +2,9 @@
 
 use crate::{
     error::{Error, Result, TransportError},
-    helpers, BatchTransport, RequestId, Transport,
+    helpers,
+    transports::ic_http::handle_batch_response,
+    BatchTransport, RequestId, Transport,
 };
 #[cfg(not(feature = "wasm"))]
 use futures::future::BoxFuture;
@@ -11,12 +13,9 @@ use futures::future::LocalBoxFuture as BoxFuture;
 use jsonrpc_core::types::{Call, Output, Request, Value};
 use reqwest::{Client, Url};
 use serde::de::DeserializeOwned;
-use std::{
-    collections::HashMap,
-    sync::{
-        atomic::{AtomicUsize, Ordering},
-        Arc,
-    },
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
 };
 
 /// HTTP Transport
@@ -94,7 +93,11 @@ async fn execute_rpc<T: DeserializeOwned>(client: &Client, url: Url, request: &R
         String::from_utf8_lossy(&response)
     );
     if !status.is_success() {
-        return Err(Error::Transport(TransportError::Code(status.as_u16())));
+        let excerpt = String::from_utf8_lossy(&response[..response.len().min(256)]).into_owned();
+        return Err(Error::Transport(match status.as_u16() {
+            429 => TransportError::RateLimited(excerpt),
+            status => TransportError::Code(status, excerpt),
+        }));
     }
     helpers::arbitrary_precision_deserialize_workaround(&response).map_err(|err| {
         Error::Transport(TransportError::Message(format!(
@@ -143,36 +146,6 @@ impl BatchTransport for Http {
     }
 }
 
-// According to the jsonrpc specification batch responses can be returned in any order so we need to
-// restore the intended order.
-fn handle_batch_response(ids: &[RequestId], outputs: Vec<Output>) -> Result<Vec<RpcResult>> {
-    if ids.len() != outputs.len() {
-        return Err(Error::InvalidResponse("unexpected number of responses".to_string()));
-    }
-    let mut outputs = outputs
-        .into_iter()
-        .map(|output| Ok((id_of_output(&output)?, helpers::to_result_from_output(output))))
-        .collect::<Result<HashMap<_, _>>>()?;
-    ids.iter()
-        .map(|id| {
-            outputs
-                .remove(id)
-                .ok_or_else(|| Error::InvalidResponse(format!("batch response is missing id {}", id)))
-        })
-        .collect()
-}
-
-fn id_of_output(output: &Output) -> Result<RequestId> {
-    let id = match output {
-        Output::Success(success) => &success.id,
-        Output::Failure(failure) => &failure.id,
-    };
-    match id {
-        jsonrpc_core::Id::Num(num) => Ok(*num as RequestId),
-        _ => Err(Error::InvalidResponse("response id is not u64".to_string())),
-    }
-}
-
 #[cfg(test)]
 mod tests {
     use super::*;
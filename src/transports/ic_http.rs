@@ -2,14 +2,19 @@
 
 use crate::{
     error::{Error, Result, TransportError},
-    helpers, BatchTransport, RequestId, Transport,
+    helpers,
+    transforms::{normalize_raw_transaction_result, RpcContext},
+    BatchTransport, RequestId, Transport,
 };
 #[cfg(not(feature = "wasm"))]
 use futures::future::BoxFuture;
 #[cfg(feature = "wasm")]
 use futures::future::LocalBoxFuture as BoxFuture;
 use jsonrpc_core::types::{Call, Output, Request, Value};
-use crate::transports::ICHttpClient;
+use crate::transports::{
+    ic_http_client::{HeaderHook, Transform, TransformHook},
+    ICHttpClient, ResponseSizePolicy,
+};
 use serde::de::DeserializeOwned;
 use std::{
     collections::HashMap,
@@ -24,6 +29,7 @@ use std::{
 pub struct ICHttp {
     client: ICHttpClient,
     inner: Arc<Inner>,
+    raw_transaction_mode: bool,
 }
 
 #[derive(Debug)]
@@ -46,25 +52,127 @@ impl ICHttp {
                     url: url.to_string(),
                     id: AtomicUsize::new(0),
                 }),
+                raw_transaction_mode: false,
             }
         )
     }
 
+    /// The URL this transport sends requests to.
+    pub fn url(&self) -> &str {
+        &self.inner.url
+    }
+
+    /// Set the number of nodes of the subnet the canister runs on, used to compute outcall cycles.
+    pub fn set_subnet_size(&mut self, n: u64) {
+        self.client.set_subnet_size(n);
+    }
+
+    /// Set the transform query method and context used for the outcalls of this transport.
+    ///
+    /// Defaults to a method named `transform` with an empty context.
+    pub fn set_transform(&mut self, method: &str, context: Vec<u8>) {
+        self.client.set_transform(Transform::new(method, context));
+    }
+
+    /// Set a hook picking the transform of each call, e.g. by JSON-RPC method or request id.
+    ///
+    /// When the hook returns `None` the transform set with [ICHttp::set_transform] is used.
+    pub fn set_transform_hook(&mut self, hook: TransformHook) {
+        self.client.set_transform_hook(Some(hook));
+    }
+
+    /// Pick the response size limit of each call by its JSON-RPC method.
+    ///
+    /// The policy replaces the fixed limit until [ICHttp::set_max_response_bytes](Transport::set_max_response_bytes)
+    /// is called again.
+    pub fn set_response_size_policy(&mut self, policy: ResponseSizePolicy) {
+        self.client.set_size_policy(Some(policy));
+    }
+
+    /// Retry outcalls rejected for exceeding the response size limit with a doubled limit, up to
+    /// the cap of the size policy, or 2MB without one. Each attempt is paid for.
+    pub fn set_retry_oversize(&mut self, enabled: bool) {
+        self.client.set_retry_oversize(enabled);
+    }
+
+    /// Add a header sent with every outcall, e.g. the API key header of a provider.
+    ///
+    /// Header values are left out of the `Debug` output. Errors only hide the values of
+    /// `Authorization`, `*-Key` and `*-Token` headers, see [add_secret_header](Self::add_secret_header).
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.client.add_header(name, value);
+    }
+
+    /// Add a header sent with every outcall whose value is also kept out of errors.
+    pub fn add_secret_header(&mut self, name: &str, value: &str) {
+        self.client.add_secret_header(name, value);
+    }
+
+    /// Authenticate every outcall with an `Authorization: Bearer` header.
+    pub fn set_bearer_auth(&mut self, token: &str) {
+        self.client.add_header("Authorization", &format!("Bearer {}", token));
+    }
+
+    /// Authenticate every outcall with an `Authorization: Basic` header.
+    pub fn set_basic_auth(&mut self, username: &str, password: &str) {
+        let credentials = base64::encode(format!("{}:{}", username, password));
+        self.client.add_header("Authorization", &format!("Basic {}", credentials));
+    }
+
+    /// Set a hook adding headers to each call, computed from its JSON-RPC payload.
+    pub fn set_header_hook(&mut self, hook: HeaderHook) {
+        self.client.set_header_hook(Some(hook));
+    }
+
+    /// Make `eth_sendRawTransaction` safe to call from every replica of the subnet.
+    ///
+    /// Each replica submits the same transaction, so only one provider call accepts it and the
+    /// others answer "already known" or "nonce too low". In this mode the transaction hash is
+    /// computed locally, the transform context of the outcall carries it so the transform maps
+    /// these answers to the hash, and the hash is what the call returns.
+    ///
+    /// The returned hash is not a proof of acceptance. "nonce too low" is also the answer when
+    /// another transaction already used the nonce, then the hash is never mined and waiting for
+    /// its confirmations never ends. Check the receipt, or the nonce of the sender, to find out.
+    ///
+    /// The transform query of the canister has to be [rpc_transform](crate::transforms::rpc_transform),
+    /// see [register_transform](crate::register_transform).
+    pub fn set_raw_transaction_mode(&mut self, enabled: bool) {
+        self.raw_transaction_mode = enabled;
+    }
+
+    /// Cycles attached to all outcalls made through this transport, see [ICHttpClient::cycles_spent].
+    pub fn cycles_spent(&self) -> u64 {
+        self.client.cycles_spent()
+    }
+
+    /// Cycles attached to the most recently started outcall, see [ICHttpClient::last_call_cycles].
+    pub fn last_call_cycles(&self) -> u64 {
+        self.client.last_call_cycles()
+    }
+
     fn next_id(&self) -> RequestId {
         self.inner.id.fetch_add(1, Ordering::AcqRel)
     }
 
-    fn new_request(&self) -> (ICHttpClient, String) {
-        (self.client.clone(), self.inner.url.clone())
+    /// Client for the given request, passing the raw transaction hashes to the transform.
+    fn client_for(&self, request: &Request) -> (ICHttpClient, RpcContext) {
+        let mut client = self.client.clone();
+        if !self.raw_transaction_mode {
+            return (client, RpcContext::default());
+        }
+        let context = RpcContext::from_request(request);
+        if !context.raw_transactions.is_empty() {
+            let method = client.transform.method.clone();
+            client.set_transform(Transform::new(&method, context.encode()));
+        }
+        (client, context)
     }
 }
 
 // Id is only used for logging.
 async fn execute_rpc<T: DeserializeOwned>(client: &ICHttpClient, url: String, request: &Request, id: RequestId) -> Result<T> {
-    let response = client
-        .post(url, request, None, None)
-        .await
-        .map_err(|err| Error::Transport(TransportError::Message(err)))?;
+    let response = client.post(url, request, None, None).await?;
     helpers::arbitrary_precision_deserialize_workaround(&response).map_err(|err| {
         Error::Transport(TransportError::Message(format!(
             "failed to deserialize response: {}: {}",
@@ -86,10 +194,18 @@ impl Transport for ICHttp {
     }
 
     fn send(&self, id: RequestId, call: Call) -> Self::Out {
-        let (client, url) = self.new_request();
+        let request = Request::Single(call);
+        let (client, context) = self.client_for(&request);
+        let url = self.inner.url.clone();
         Box::pin(async move {
-            let output: Output = execute_rpc(&client, url, &Request::Single(call), id).await?;
-            helpers::to_result_from_output(output)
+            let result = match execute_rpc(&client, url, &request, id).await {
+                Ok(output) => helpers::to_result_from_output(output),
+                Err(err) => Err(err),
+            };
+            match context.raw_transaction(None) {
+                Some(hash) => normalize_raw_transaction_result(result, hash),
+                None => result,
+            }
         })
     }
 
@@ -107,18 +223,28 @@ impl BatchTransport for ICHttp {
     {
         // Batch calls don't need an id but it helps associate the response log with the request log.
         let id = self.next_id();
-        let (client, url) = self.new_request();
         let (ids, calls): (Vec<_>, Vec<_>) = requests.into_iter().unzip();
+        let request = Request::Batch(calls);
+        let (client, context) = self.client_for(&request);
+        let url = self.inner.url.clone();
         Box::pin(async move {
-            let outputs: Vec<Output> = execute_rpc(&client, url, &Request::Batch(calls), id).await?;
-            handle_batch_response(&ids, outputs)
+            let outputs: Vec<Output> = execute_rpc(&client, url, &request, id).await?;
+            let results = handle_batch_response(&ids, outputs)?;
+            Ok(ids
+                .iter()
+                .zip(results)
+                .map(|(id, result)| match context.raw_transaction(Some(*id as u64)) {
+                    Some(hash) => normalize_raw_transaction_result(result, hash),
+                    None => result,
+                })
+                .collect())
         })
     }
 }
 
 // According to the jsonrpc specification batch responses can be returned in any order so we need to
 // restore the intended order.
-fn handle_batch_response(ids: &[RequestId], outputs: Vec<Output>) -> Result<Vec<RpcResult>> {
+pub(crate) fn handle_batch_response(ids: &[RequestId], outputs: Vec<Output>) -> Result<Vec<RpcResult>> {
     if ids.len() != outputs.len() {
         return Err(Error::InvalidResponse("unexpected number of responses".to_string()));
     }
@@ -150,48 +276,6 @@ fn id_of_output(output: &Output) -> Result<RequestId> {
 mod tests {
     use super::*;
 
-    async fn server(req: hyper::Request<hyper::Body>) -> hyper::Result<hyper::Response<hyper::Body>> {
-        use hyper::body::HttpBody;
-
-        let expected = r#"{"jsonrpc":"2.0","method":"eth_getAccounts","params":[],"id":0}"#;
-        let response = r#"{"jsonrpc":"2.0","id":0,"result":"x"}"#;
-
-        assert_eq!(req.method(), &hyper::Method::POST);
-        assert_eq!(req.uri().path(), "/");
-        let mut content: Vec<u8> = vec![];
-        let mut body = req.into_body();
-        while let Some(Ok(chunk)) = body.data().await {
-            content.extend(&*chunk);
-        }
-        assert_eq!(std::str::from_utf8(&*content), Ok(expected));
-
-        Ok(hyper::Response::new(response.into()))
-    }
-
-    #[tokio::test]
-    async fn should_make_a_request() {
-        use hyper::service::{make_service_fn, service_fn};
-
-        // given
-        let addr = "127.0.0.1:3001";
-        // start server
-        let service = make_service_fn(|_| async { Ok::<_, hyper::Error>(service_fn(server)) });
-        let server = hyper::Server::bind(&addr.parse().unwrap()).serve(service);
-        tokio::spawn(async move {
-            println!("Listening on http://{}", addr);
-            server.await.unwrap();
-        });
-
-        // when
-        let client = Http::new(&format!("http://{}", addr)).unwrap();
-        println!("Sending request");
-        let response = client.execute("eth_getAccounts", vec![]).await;
-        println!("Got response");
-
-        // then
-        assert_eq!(response, Ok(Value::String("x".into())));
-    }
-
     #[test]
     fn handles_batch_response_being_in_different_order_than_input() {
         let ids = vec![0, 1, 2];
@@ -1,14 +1,28 @@
 //! IC http client
 
+use crate::error::{Error, Result, TransportError};
+use crate::transports::response_size::{is_oversize, ResponseSizePolicy};
 use serde::{self, Deserialize, Serialize};
 use candid::CandidType;
 use jsonrpc_core::Request;
 use candid::{Principal, candid_method};
+use ic_cdk::api::call::{call_with_payment128, CallResult};
 use ic_cdk::api::management_canister::http_request::{
     CanisterHttpRequestArgument, HttpHeader, HttpMethod, 
-    HttpResponse, http_request,
+    HttpResponse,
     TransformFunc, TransformContext, 
 };
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+/// Number of nodes of a regular application subnet.
+pub const DEFAULT_SUBNET_SIZE: u64 = 13;
+/// Response size limit of an outcall when `max_response_bytes` is not set.
+const MAX_RESPONSE_BYTES: u64 = 2_000_000;
+/// Length of the body excerpt attached to HTTP status errors.
+const BODY_EXCERPT_LEN: usize = 256;
 
 // #[derive(CandidType, Deserialize, Debug)]
 // pub struct CanisterHttpRequestArgs {
@@ -20,20 +34,160 @@ use ic_cdk::api::management_canister::http_request::{
 //     pub transform_method_name: Option<String>,
 // }
 
-#[derive(Clone, Debug)]
+/// Cycles required for an http outcall on a subnet of `subnet_size` nodes.
+///
+/// The request size counts the url, headers, body, transform method name and transform context.
+/// Refer to https://internetcomputer.org/docs/current/developer-docs/gas-cost
+pub fn http_request_required_cycles(request: &CanisterHttpRequestArgument, subnet_size: u64) -> u128 {
+    let headers_bytes: usize = request.headers.iter().map(|h| h.name.len() + h.value.len()).sum();
+    let transform_bytes = request
+        .transform
+        .as_ref()
+        .map(|t| t.function.0.method.len() + t.context.len())
+        .unwrap_or_default();
+    let request_bytes = (request.url.len()
+        + headers_bytes
+        + request.body.as_ref().map(Vec::len).unwrap_or_default()
+        + transform_bytes) as u128;
+    let response_bytes = request.max_response_bytes.unwrap_or(MAX_RESPONSE_BYTES) as u128;
+    let n = subnet_size as u128;
+
+    (3_000_000 + 60_000 * n) * n + 400 * n * request_bytes + 800 * n * response_bytes
+}
+
+/// Canister query method applied to outcall responses and the context passed to it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    /// Name of the transform query method of this canister.
+    pub method: String,
+    /// Arbitrary bytes handed to the method in `TransformArgs::context`.
+    pub context: Vec<u8>,
+}
+
+impl Transform {
+    pub fn new(method: &str, context: Vec<u8>) -> Self {
+        Transform {
+            method: method.to_string(),
+            context,
+        }
+    }
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::new("transform", vec![])
+    }
+}
+
+/// Picks the transform of a single outcall from its JSON-RPC payload.
+///
+/// Returning `None` falls back to the transform configured on the client.
+pub type TransformHook = fn(&Request) -> Option<Transform>;
+
+/// Extra headers of a single outcall, computed from its JSON-RPC payload.
+pub type HeaderHook = fn(&Request) -> Vec<HttpHeader>;
+
+#[derive(Clone)]
 pub struct ICHttpClient {
     pub max_response_bytes: u64,
+    /// Number of nodes of the subnet the canister runs on, used to compute outcall cycles.
+    pub subnet_size: u64,
+    /// Transform used for every outcall unless `transform_hook` picks another one.
+    pub transform: Transform,
+    pub transform_hook: Option<TransformHook>,
+    /// Headers sent with every outcall, e.g. `Authorization`.
+    pub headers: Vec<HttpHeader>,
+    /// Lowercase names of headers whose values are kept out of errors, besides auth-like ones.
+    pub secret_headers: Vec<String>,
+    pub header_hook: Option<HeaderHook>,
+    /// Per-method response size limits, replacing `max_response_bytes` when set.
+    pub size_policy: Option<ResponseSizePolicy>,
+    /// Retry outcalls rejected for an oversize response with a doubled limit, up to the cap.
+    pub retry_oversize: bool,
+    cycles_spent: Arc<AtomicU64>,
+    last_call_cycles: Arc<AtomicU64>,
 }
 
 impl ICHttpClient {
     pub fn new(max_resp: Option<u64>) -> Self {
         ICHttpClient {
             max_response_bytes: if let Some(v) = max_resp { v } else { 500_000 },
+            subnet_size: DEFAULT_SUBNET_SIZE,
+            transform: Transform::default(),
+            transform_hook: None,
+            headers: vec![],
+            secret_headers: vec![],
+            header_hook: None,
+            size_policy: None,
+            retry_oversize: false,
+            cycles_spent: Default::default(),
+            last_call_cycles: Default::default(),
         }
     }
 
+    /// Set a fixed response size limit, dropping the size policy.
     pub fn set_max_response_bytes(&mut self, v: u64) {
         self.max_response_bytes = v;
+        self.size_policy = None;
+    }
+
+    pub fn set_size_policy(&mut self, policy: Option<ResponseSizePolicy>) {
+        self.size_policy = policy;
+    }
+
+    pub fn set_retry_oversize(&mut self, enabled: bool) {
+        self.retry_oversize = enabled;
+    }
+
+    pub fn set_subnet_size(&mut self, n: u64) {
+        self.subnet_size = n;
+    }
+
+    pub fn set_transform(&mut self, transform: Transform) {
+        self.transform = transform;
+    }
+
+    pub fn set_transform_hook(&mut self, hook: Option<TransformHook>) {
+        self.transform_hook = hook;
+    }
+
+    /// Add a header sent with every outcall, replacing any header of the same name.
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.retain(|h| !h.name.eq_ignore_ascii_case(name));
+        self.headers.push(HttpHeader {
+            name: name.to_string(),
+            value: value.to_string(),
+        });
+    }
+
+    /// Add a header sent with every outcall whose value is kept out of errors, e.g. an API key
+    /// under a custom name. `Authorization`, `*-Key` and `*-Token` headers are secret anyway.
+    pub fn add_secret_header(&mut self, name: &str, value: &str) {
+        self.add_header(name, value);
+        let name = name.to_ascii_lowercase();
+        if !self.secret_headers.contains(&name) {
+            self.secret_headers.push(name);
+        }
+    }
+
+    pub fn set_header_hook(&mut self, hook: Option<HeaderHook>) {
+        self.header_hook = hook;
+    }
+
+    /// Cycles attached to all outcalls made by this client and its clones, saturating at `u64::MAX`.
+    ///
+    /// A best-effort aggregate: refunds of unused cycles are not subtracted.
+    pub fn cycles_spent(&self) -> u64 {
+        self.cycles_spent.load(Ordering::Acquire)
+    }
+
+    /// Cycles attached to the most recently started outcall of this client and its clones,
+    /// saturating at `u64::MAX`.
+    ///
+    /// With concurrent calls, this is the cost of whichever outcall started last, not necessarily
+    /// the one just awaited.
+    pub fn last_call_cycles(&self) -> u64 {
+        self.last_call_cycles.load(Ordering::Acquire)
     }
 
     async fn request(
@@ -44,10 +198,19 @@ impl ICHttpClient {
         payload: &Request,
         max_resp: Option<u64>,
         cycles: Option<u64>
-    ) -> Result<Vec<u8>, String> {
-        let request = CanisterHttpRequestArgument {
+    ) -> Result<Vec<u8>> {
+        let mut req_headers = req_headers;
+        req_headers.extend(self.headers.iter().cloned());
+        if let Some(hook) = self.header_hook {
+            req_headers.extend(hook(payload));
+        }
+        let transform = self
+            .transform_hook
+            .and_then(|hook| hook(payload))
+            .unwrap_or_else(|| self.transform.clone());
+        let mut request = CanisterHttpRequestArgument {
             url: url.clone(),
-            max_response_bytes: if let Some(v) = max_resp { Some(v) } else { Some(self.max_response_bytes) },
+            max_response_bytes: None,
             method: req_type,
             headers: req_headers,
             body: Some(serde_json::to_vec(&payload).unwrap()),
@@ -58,26 +221,60 @@ impl ICHttpClient {
             transform: Some(TransformContext {
                 function: TransformFunc(candid::Func {
                         principal: ic_cdk::api::id(),
-                        method: "transform".to_string(),
+                        method: transform.method,
                     }),
-                context: vec![],
+                context: transform.context,
             }),
         };
 
-        match http_request(request).await {
-            Ok((result, )) => {
-                Ok(result.body)
+        let mut limit = match (max_resp, &self.size_policy) {
+            (Some(v), _) => v,
+            (None, Some(policy)) => policy.for_request(payload),
+            (None, None) => self.max_response_bytes,
+        };
+        loop {
+            request.max_response_bytes = Some(limit);
+            match self.outcall(request.clone(), cycles).await {
+                Err(err) if self.retry_oversize && is_oversize(&err) => {
+                    let cap = self.size_policy.as_ref().map(ResponseSizePolicy::cap).unwrap_or(MAX_RESPONSE_BYTES);
+                    match ResponseSizePolicy::new(limit, cap).grow(limit) {
+                        Some(next) => limit = next,
+                        None => return Err(err),
+                    }
+                }
+                res => return res,
             }
+        }
+    }
+
+    async fn outcall(&self, request: CanisterHttpRequestArgument, cycles: Option<u64>) -> Result<Vec<u8>> {
+        // unused cycles are refunded, but the computed amount is exact for the request
+        let cycles = cycles
+            .map(u128::from)
+            .unwrap_or_else(|| http_request_required_cycles(&request, self.subnet_size));
+        let attached = u64::try_from(cycles).unwrap_or(u64::MAX);
+        self.last_call_cycles.store(attached, Ordering::Release);
+        let _ = self
+            .cycles_spent
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |spent| Some(spent.saturating_add(attached)));
+
+        let headers = request.headers.clone();
+        let response: CallResult<(HttpResponse,)> =
+            call_with_payment128(Principal::management_canister(), "http_request", (request,), cycles).await;
+        match response {
+            Ok((result, )) => check_status(result),
             Err((r, m)) => {
+                // header values hold api keys, keep them out of logs and errors
+                let m = redact(m, &headers, &self.secret_headers);
                 let message =
                     format!("The http_request resulted into error. RejectionCode: {r:?}, Error: {m}");
-                ic_cdk::api::print(message.clone());
-                Err(message)
+                ic_cdk::api::print(message);
+                Err(Error::Transport(TransportError::Rejected(r, m)))
             }
         }
     }
 
-    pub async fn get(&self, url: String, payload: &Request, max_resp: Option<u64>, cycles: Option<u64>) -> Result<Vec<u8>, String> {
+    pub async fn get(&self, url: String, payload: &Request, max_resp: Option<u64>, cycles: Option<u64>) -> Result<Vec<u8>> {
         let request_headers = vec![
             HttpHeader {
                 name: "Content-Type".to_string(),
@@ -88,7 +285,7 @@ impl ICHttpClient {
         self.request(url, HttpMethod::GET, request_headers, payload, max_resp, cycles).await
     }
 
-    pub async fn post(&self, url: String, payload: &Request, max_resp: Option<u64>, cycles: Option<u64>) -> Result<Vec<u8>, String> {
+    pub async fn post(&self, url: String, payload: &Request, max_resp: Option<u64>, cycles: Option<u64>) -> Result<Vec<u8>> {
         let request_headers = vec![
             HttpHeader {
                 name: "Content-Type".to_string(),
@@ -100,3 +297,129 @@ impl ICHttpClient {
     }
 }
 
+impl std::fmt::Debug for ICHttpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ICHttpClient")
+            .field("max_response_bytes", &self.max_response_bytes)
+            .field("subnet_size", &self.subnet_size)
+            .field("transform", &self.transform)
+            .field("size_policy", &self.size_policy)
+            .field("retry_oversize", &self.retry_oversize)
+            .field("headers", &self.headers.iter().map(|h| &h.name).collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+/// Maps non-2xx responses to `TransportError::Code`, or `TransportError::RateLimited` for 429.
+fn check_status(response: HttpResponse) -> Result<Vec<u8>> {
+    let status = u16::try_from(&response.status.0).unwrap_or(u16::MAX);
+    if (200..300).contains(&status) {
+        return Ok(response.body);
+    }
+    let end = response.body.len().min(BODY_EXCERPT_LEN);
+    let excerpt = String::from_utf8_lossy(&response.body[..end]).into_owned();
+    Err(Error::Transport(match status {
+        429 => TransportError::RateLimited(excerpt),
+        status => TransportError::Code(status, excerpt),
+    }))
+}
+
+/// Whether the value of the header `name` holds credentials.
+fn is_secret(name: &str, secret_headers: &[String]) -> bool {
+    let name = name.to_ascii_lowercase();
+    name == "authorization"
+        || name == "proxy-authorization"
+        || name.ends_with("-key")
+        || name.ends_with("-token")
+        || secret_headers.contains(&name)
+}
+
+/// Replaces the value of every secret header occurring in `message`, and the credentials of
+/// values like `Bearer <token>` on their own.
+fn redact(message: String, headers: &[HttpHeader], secret_headers: &[String]) -> String {
+    headers
+        .iter()
+        .filter(|h| is_secret(&h.name, secret_headers))
+        .flat_map(|h| [h.value.as_str(), h.value.split_whitespace().last().unwrap_or_default()])
+        .filter(|secret| !secret.is_empty())
+        .fold(message, |message, secret| message.replace(secret, "<redacted>"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_keep_header_values_out_of_debug_and_errors() {
+        let mut client = ICHttpClient::new(None);
+        client.add_header("Authorization", "Bearer secret-key");
+        client.add_header("authorization", "Bearer other-key");
+        assert_eq!(client.headers.len(), 1);
+        assert!(!format!("{:?}", client).contains("other-key"));
+        assert_eq!(
+            redact("bad header Bearer other-key".into(), &client.headers, &client.secret_headers),
+            "bad header <redacted>"
+        );
+        assert_eq!(
+            redact("unknown key other-key".into(), &client.headers, &client.secret_headers),
+            "unknown key <redacted>"
+        );
+    }
+
+    #[test]
+    fn should_only_redact_secret_headers() {
+        let mut client = ICHttpClient::new(None);
+        client.add_header("Content-Type", "application/json");
+        client.add_header("X-Api-Key", "key-1");
+        client.add_secret_header("X-Project", "project-1");
+        let message = "expected application/json with key-1 for project-1".to_string();
+        assert_eq!(
+            redact(message, &client.headers, &client.secret_headers),
+            "expected application/json with <redacted> for <redacted>"
+        );
+    }
+
+    fn response(status: u16, body: &str) -> HttpResponse {
+        HttpResponse {
+            status: candid::Nat::from(status as u64),
+            headers: vec![],
+            body: body.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn should_map_http_status() {
+        assert_eq!(check_status(response(200, "{}")), Ok(b"{}".to_vec()));
+        assert_eq!(
+            check_status(response(503, "upstream unavailable")),
+            Err(Error::Transport(TransportError::Code(503, "upstream unavailable".into())))
+        );
+        let limited = check_status(response(429, "slow down")).unwrap_err();
+        assert_eq!(limited, Error::Transport(TransportError::RateLimited("slow down".into())));
+        assert!(limited.is_rate_limited());
+
+        let long = "x".repeat(1000);
+        match check_status(response(500, &long)) {
+            Err(Error::Transport(TransportError::Code(500, excerpt))) => assert_eq!(excerpt.len(), BODY_EXCERPT_LEN),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[test]
+    fn should_compute_outcall_cycles() {
+        let request = CanisterHttpRequestArgument {
+            url: "https://rpc".to_string(),
+            max_response_bytes: Some(100),
+            method: HttpMethod::POST,
+            headers: vec![],
+            body: Some(vec![0u8; 9]),
+            transform: None,
+        };
+        // base fee + 20 request bytes + 100 response bytes on a 13 node subnet
+        assert_eq!(http_request_required_cycles(&request, 13), 49_140_000 + 104_000 + 1_040_000);
+        assert_eq!(
+            http_request_required_cycles(&request, 34),
+            (3_000_000 + 60_000 * 34) * 34 + 400 * 34 * 20 + 800 * 34 * 100
+        );
+    }
+}
@@ -0,0 +1,313 @@
+//! IC HTTP provider pool
+
+use crate::{
+    error::{Error, Result, TransportError},
+    helpers, rpc,
+    transports::ICHttp,
+    BatchTransport, RequestId, Transport,
+};
+use candid::CandidType;
+#[cfg(not(feature = "wasm"))]
+use futures::future::BoxFuture;
+#[cfg(feature = "wasm")]
+use futures::future::LocalBoxFuture as BoxFuture;
+use serde::Deserialize;
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Consecutive failures after which a provider is skipped.
+const DEFAULT_MAX_FAILURES: u32 = 3;
+/// Time after which a skipped provider is tried again, in nanoseconds.
+const DEFAULT_COOLDOWN: u64 = 60_000_000_000;
+
+thread_local! {
+    static HEALTH: RefCell<HashMap<String, ProviderHealth>> = RefCell::new(HashMap::new());
+    static CURSORS: RefCell<HashMap<String, usize>> = RefCell::new(HashMap::new());
+}
+
+/// Health of a single provider.
+///
+/// It is kept in a thread local keyed by URL, so it is shared by all pools using that URL and
+/// survives between calls, but not upgrades. See [providers_health] to save it.
+#[derive(CandidType, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct ProviderHealth {
+    /// Number of consecutive failed calls.
+    pub failures: u32,
+    /// IC time of the last failure, in nanoseconds.
+    pub last_failure: u64,
+    /// Total number of successful calls.
+    pub successes: u64,
+}
+
+impl ProviderHealth {
+    fn is_healthy(&self, now: u64, max_failures: u32, cooldown: u64) -> bool {
+        self.failures < max_failures || now.saturating_sub(self.last_failure) >= cooldown
+    }
+}
+
+/// Returns the recorded health of every provider used so far.
+pub fn providers_health() -> Vec<(String, ProviderHealth)> {
+    HEALTH.with(|h| h.borrow().iter().map(|(url, health)| (url.clone(), health.clone())).collect())
+}
+
+/// Forgets the recorded health of every provider.
+pub fn reset_providers_health() {
+    HEALTH.with(|h| h.borrow_mut().clear());
+}
+
+fn record_success(url: &str) {
+    HEALTH.with(|h| {
+        let mut h = h.borrow_mut();
+        let health = h.entry(url.to_string()).or_default();
+        health.failures = 0;
+        health.successes += 1;
+    });
+}
+
+fn record_failure(url: &str, now: u64) {
+    HEALTH.with(|h| {
+        let mut h = h.borrow_mut();
+        let health = h.entry(url.to_string()).or_default();
+        health.failures += 1;
+        health.last_failure = now;
+    });
+}
+
+/// How the pool picks the first provider of each call.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Strategy {
+    /// Always start from the first healthy provider in the list.
+    Failover,
+    /// Rotate the starting provider on every call.
+    RoundRobin,
+}
+
+/// Pool of `ICHttp` providers.
+///
+/// Calls go to one provider at a time. When the outcall is rejected (timeouts, unreachable
+/// hosts), the provider answers with a 5xx status or a rate-limit error, the call is retried
+/// on the next healthy provider. Other errors, e.g. JSON-RPC errors, are returned as they are and
+/// do not count against the provider.
+///
+/// Provider health and the round-robin cursor live in thread locals, keyed by URL and by the list
+/// of URLs of the pool respectively. They are shared by all pools with the same URLs and are not
+/// persisted across upgrades.
+#[derive(Clone, Debug)]
+pub struct ICHttpPool {
+    providers: Vec<ICHttp>,
+    strategy: Strategy,
+    max_failures: u32,
+    cooldown: u64,
+    id: Arc<AtomicUsize>,
+}
+
+impl ICHttpPool {
+    /// Create new pool over the given ordered list of URLs.
+    pub fn new(urls: &[&str], strategy: Strategy, max_resp: Option<u64>) -> Result<Self> {
+        if urls.is_empty() {
+            return Err(Error::Transport(TransportError::Message("provider pool is empty".into())));
+        }
+        let providers = urls
+            .iter()
+            .map(|url| ICHttp::new(url, max_resp))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Self {
+            providers,
+            strategy,
+            max_failures: DEFAULT_MAX_FAILURES,
+            cooldown: DEFAULT_COOLDOWN,
+            id: Default::default(),
+        })
+    }
+
+    /// Set the number of consecutive failures after which a provider is skipped.
+    pub fn set_max_failures(&mut self, max_failures: u32) {
+        self.max_failures = max_failures;
+    }
+
+    /// Set the time in nanoseconds after which a skipped provider is tried again.
+    pub fn set_cooldown(&mut self, cooldown: u64) {
+        self.cooldown = cooldown;
+    }
+
+    /// Borrows the providers of this pool.
+    pub fn providers(&self) -> &[ICHttp] {
+        &self.providers
+    }
+
+    fn next_id(&self) -> RequestId {
+        self.id.fetch_add(1, Ordering::AcqRel)
+    }
+
+    fn key(&self) -> String {
+        self.providers.iter().map(ICHttp::url).collect::<Vec<_>>().join(",")
+    }
+
+    /// Order in which providers are tried for the next call.
+    fn order(&self) -> Vec<usize> {
+        let start = match self.strategy {
+            Strategy::Failover => 0,
+            Strategy::RoundRobin => CURSORS.with(|c| {
+                let mut cursors = c.borrow_mut();
+                let cursor = cursors.entry(self.key()).or_default();
+                let start = *cursor;
+                *cursor = (start + 1) % self.providers.len();
+                start
+            }),
+        };
+        let now = ic_cdk::api::time();
+        let healthy = HEALTH.with(|h| {
+            let h = h.borrow();
+            self.providers
+                .iter()
+                .map(|p| {
+                    h.get(p.url())
+                        .map(|health| health.is_healthy(now, self.max_failures, self.cooldown))
+                        .unwrap_or(true)
+                })
+                .collect::<Vec<_>>()
+        });
+        order(start, &healthy)
+    }
+
+    async fn with_failover<R>(&self, call: impl Fn(&ICHttp) -> BoxFuture<'static, Result<R>>) -> Result<R> {
+        let mut last_error = Error::Unreachable;
+        for idx in self.order() {
+            let provider = &self.providers[idx];
+            match call(provider).await {
+                Err(err) if should_failover(&err) => {
+                    record_failure(provider.url(), ic_cdk::api::time());
+                    last_error = err;
+                }
+                Ok(res) => {
+                    record_success(provider.url());
+                    return Ok(res);
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        Err(last_error)
+    }
+}
+
+/// Healthy providers first, starting at `start` and wrapping around, then the unhealthy ones
+/// as a last resort.
+fn order(start: usize, healthy: &[bool]) -> Vec<usize> {
+    let len = healthy.len();
+    let rotated = (0..len).map(|i| (start + i) % len);
+    let (mut first, last): (Vec<_>, Vec<_>) = rotated.partition(|&i| healthy[i]);
+    first.extend(last);
+    first
+}
+
+fn should_failover(err: &Error) -> bool {
+    match err {
+        Error::Transport(TransportError::Rejected(..)) => true,
+        Error::Transport(TransportError::Code(status, _)) => *status >= 500,
+        err => err.is_rate_limited(),
+    }
+}
+
+impl Transport for ICHttpPool {
+    type Out = BoxFuture<'static, Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.next_id();
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, id: RequestId, call: rpc::Call) -> Self::Out {
+        let pool = self.clone();
+        Box::pin(async move { pool.with_failover(|provider| provider.send(id, call.clone())).await })
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        for provider in self.providers.iter_mut() {
+            provider.set_max_response_bytes(v);
+        }
+    }
+}
+
+impl BatchTransport for ICHttpPool {
+    type Batch = BoxFuture<'static, Result<Vec<Result<rpc::Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let pool = self.clone();
+        let requests = requests.into_iter().collect::<Vec<_>>();
+        Box::pin(async move {
+            pool.with_failover(|provider| {
+                let batch = provider.send_batch(requests.clone());
+                let checked: BoxFuture<'static, Result<Vec<Result<rpc::Value>>>> = Box::pin(async move {
+                    let results = batch.await?;
+                    // a rate limited batch is retried as a whole on the next provider
+                    match results.iter().find_map(|res| res.as_ref().err().filter(|e| e.is_rate_limited())) {
+                        Some(err) => Err(err.clone()),
+                        None => Ok(results),
+                    }
+                });
+                checked
+            })
+            .await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_try_healthy_providers_first() {
+        assert_eq!(order(0, &[true, false, true]), vec![0, 2, 1]);
+        assert_eq!(order(2, &[true, true, true]), vec![2, 0, 1]);
+        assert_eq!(order(1, &[false, false, true]), vec![2, 1, 0]);
+    }
+
+    #[test]
+    fn should_recover_after_cooldown() {
+        let health = ProviderHealth {
+            failures: 3,
+            last_failure: 100,
+            successes: 0,
+        };
+        assert!(!health.is_healthy(150, 3, 100));
+        assert!(health.is_healthy(200, 3, 100));
+    }
+
+    #[test]
+    fn should_failover_on_rejections_and_rate_limits() {
+        use ic_cdk::api::call::RejectionCode;
+
+        let rejected = Error::Transport(TransportError::Rejected(RejectionCode::SysTransient, "Timeout".into()));
+        let mut rate_limited = rpc::Error::new(rpc::ErrorCode::ServerError(-32005));
+        rate_limited.message = "daily request count exceeded, request rate limited".into();
+
+        assert!(should_failover(&rejected));
+        assert!(should_failover(&Error::Transport(TransportError::Code(502, "".into()))));
+        assert!(!should_failover(&Error::Transport(TransportError::Code(401, "".into()))));
+        assert!(should_failover(&Error::Transport(TransportError::RateLimited("".into()))));
+        assert!(should_failover(&Error::Rpc(rate_limited)));
+        assert!(!should_failover(&Error::Rpc(rpc::Error::invalid_params("bad block"))));
+    }
+
+    #[test]
+    fn should_not_failover_on_too_large_queries() {
+        let mut too_large = rpc::Error::new(rpc::ErrorCode::ServerError(-32005));
+        too_large.message = "query returned more than 10000 results".into();
+
+        let err = Error::Rpc(too_large);
+        assert!(err.is_too_large());
+        assert!(!err.is_rate_limited());
+        assert!(!should_failover(&err));
+    }
+}
@@ -0,0 +1,185 @@
+//! RPC proxy canister transport
+
+use crate::{
+    error::{Error, Result, TransportError},
+    helpers,
+    transports::ic_http::handle_batch_response,
+    BatchTransport, RequestId, Transport,
+};
+use candid::{CandidType, Principal};
+#[cfg(not(feature = "wasm"))]
+use futures::future::BoxFuture;
+#[cfg(feature = "wasm")]
+use futures::future::LocalBoxFuture as BoxFuture;
+use ic_cdk::api::call::{call_with_payment128, CallResult};
+use jsonrpc_core::types::{Call, Output, Request, Value};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+/// Cycles attached to each proxy call when not set otherwise. The proxy refunds what it
+/// does not charge.
+const DEFAULT_CYCLES: u128 = 10_000_000_000;
+
+/// Provider registered on the proxy canister by its owner.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Registered {
+    pub chain_id: u64,
+    pub api_provider: String,
+}
+
+/// Provider the proxy canister sends a call to.
+#[derive(CandidType, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub enum RpcTarget {
+    /// A provider registered on the proxy, whose API key only the proxy knows.
+    #[serde(rename = "registered")]
+    Registered(Registered),
+    /// A provider URL, including its API key.
+    #[serde(rename = "url_with_api_key")]
+    UrlWithApiKey(String),
+}
+
+/// Transport calling `json_rpc(payload, target, max_response_bytes)` on an RPC proxy canister,
+/// like the `endpoint` example.
+///
+/// The proxy makes the http outcall and holds the API keys, so canisters using it need
+/// neither.
+#[derive(Clone, Debug)]
+pub struct ICProxy {
+    canister: Principal,
+    target: RpcTarget,
+    max_response_bytes: Option<u64>,
+    cycles: u128,
+    id: Arc<AtomicUsize>,
+}
+
+impl ICProxy {
+    /// Create new proxy transport sending calls to `target` through the `canister`.
+    pub fn new(canister: Principal, target: RpcTarget) -> Self {
+        ICProxy {
+            canister,
+            target,
+            max_response_bytes: None,
+            cycles: DEFAULT_CYCLES,
+            id: Default::default(),
+        }
+    }
+
+    /// Set the cycles attached to each call of the proxy.
+    pub fn set_cycles(&mut self, cycles: u128) {
+        self.cycles = cycles;
+    }
+
+    /// The proxy canister.
+    pub fn canister(&self) -> Principal {
+        self.canister
+    }
+
+    /// The provider calls are sent to.
+    pub fn target(&self) -> &RpcTarget {
+        &self.target
+    }
+
+    fn next_id(&self) -> RequestId {
+        self.id.fetch_add(1, Ordering::AcqRel)
+    }
+
+    async fn execute<T: DeserializeOwned>(&self, request: &Request) -> Result<T> {
+        let payload = serde_json::to_string(request)?;
+        let reply: CallResult<(std::result::Result<String, String>,)> = call_with_payment128(
+            self.canister,
+            "json_rpc",
+            (payload, self.target.clone(), self.max_response_bytes),
+            self.cycles,
+        )
+        .await;
+        let body = into_body(reply)?;
+        helpers::arbitrary_precision_deserialize_workaround(body.as_bytes()).map_err(|err| {
+            Error::Transport(TransportError::Message(format!(
+                "failed to deserialize response: {}: {}",
+                err, body
+            )))
+        })
+    }
+}
+
+/// Body of the provider response from the reply of the proxy.
+fn into_body(reply: CallResult<(std::result::Result<String, String>,)>) -> Result<String> {
+    match reply {
+        Ok((Ok(body),)) => Ok(body),
+        Ok((Err(message),)) => Err(Error::Transport(TransportError::Message(message))),
+        Err((code, message)) => Err(Error::Transport(TransportError::Rejected(code, message))),
+    }
+}
+
+impl Transport for ICProxy {
+    type Out = BoxFuture<'static, Result<Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<Value>) -> (RequestId, Call) {
+        let id = self.next_id();
+        let request = helpers::build_request(id, method, params);
+        (id, request)
+    }
+
+    fn send(&self, id: RequestId, call: Call) -> Self::Out {
+        let proxy = self.clone();
+        Box::pin(async move {
+            let output: Output = proxy.execute(&Request::Single(call)).await?;
+            helpers::to_result_from_output(output)
+        })
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.max_response_bytes = Some(v);
+    }
+}
+
+impl BatchTransport for ICProxy {
+    type Batch = BoxFuture<'static, Result<Vec<Result<Value>>>>;
+
+    fn send_batch<T>(&self, requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, Call)>,
+    {
+        let proxy = self.clone();
+        let (ids, calls): (Vec<_>, Vec<_>) = requests.into_iter().unzip();
+        Box::pin(async move {
+            let outputs: Vec<Output> = proxy.execute(&Request::Batch(calls)).await?;
+            handle_batch_response(&ids, outputs)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ic_cdk::api::call::RejectionCode;
+
+    #[test]
+    fn should_map_proxy_replies() {
+        assert_eq!(into_body(Ok((Ok("{}".into()),))), Ok("{}".to_string()));
+        assert_eq!(
+            into_body(Ok((Err("url is empty".into()),))),
+            Err(Error::Transport(TransportError::Message("url is empty".into())))
+        );
+        assert_eq!(
+            into_body(Err((RejectionCode::CanisterError, "out of cycles".into()))),
+            Err(Error::Transport(TransportError::Rejected(
+                RejectionCode::CanisterError,
+                "out of cycles".into()
+            )))
+        );
+    }
+
+    #[test]
+    fn should_encode_targets_like_the_proxy() {
+        let target = RpcTarget::Registered(Registered {
+            chain_id: 1,
+            api_provider: "alchemy".into(),
+        });
+        let bytes = candid::encode_one(&target).unwrap();
+        assert_eq!(candid::decode_one::<RpcTarget>(&bytes).unwrap(), target);
+    }
+}
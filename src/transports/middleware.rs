@@ -0,0 +1,336 @@
+//! Request/response middleware for transports
+
+use crate::{
+    error::{self, Error, TransportError},
+    rpc, BatchTransport, RequestId, Transport,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use parking_lot::Mutex;
+use std::{
+    collections::{BTreeMap, HashSet},
+    sync::Arc,
+};
+
+/// Hooks run around every call of a [Layered] transport.
+///
+/// All hooks default to passing the call through unchanged, so a middleware only implements
+/// the ones it needs. Middlewares compose by nesting layers; the outermost layer sees the call
+/// first and the result last.
+pub trait Middleware: std::fmt::Debug + Clone {
+    /// Inspects or rewrites a call before it is sent.
+    fn on_request(&self, _id: RequestId, call: rpc::Call) -> rpc::Call {
+        call
+    }
+
+    /// Answers a call without sending it, e.g. from a cache or to refuse it.
+    fn intercept(&self, _id: RequestId, _call: &rpc::Call) -> Option<error::Result<rpc::Value>> {
+        None
+    }
+
+    /// Inspects or rewrites the result of a call, including intercepted ones.
+    fn on_response(
+        &self,
+        _id: RequestId,
+        _call: &rpc::Call,
+        result: error::Result<rpc::Value>,
+    ) -> error::Result<rpc::Value> {
+        result
+    }
+}
+
+/// Transport running a [Middleware] around the calls of another transport.
+#[derive(Debug, Clone)]
+pub struct Layered<T, M> {
+    transport: T,
+    middleware: M,
+}
+
+impl<T: Transport, M: Middleware> Layered<T, M> {
+    /// Wrap `transport` with `middleware`.
+    pub fn new(transport: T, middleware: M) -> Self {
+        Layered { transport, middleware }
+    }
+
+    /// Borrows the inner transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    /// Borrows the middleware.
+    pub fn middleware(&self) -> &M {
+        &self.middleware
+    }
+}
+
+impl<T, M> Transport for Layered<T, M>
+where
+    T: Transport,
+    T::Out: 'static + Send,
+    M: Middleware + Send + 'static,
+{
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let middleware = self.middleware.clone();
+        let request = middleware.on_request(id, request);
+        if let Some(result) = middleware.intercept(id, &request) {
+            return future::ready(middleware.on_response(id, &request, result)).boxed();
+        }
+        let answer = self.transport.send(id, request.clone());
+        answer
+            .map(move |result| middleware.on_response(id, &request, result))
+            .boxed()
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.transport.set_max_response_bytes(v);
+    }
+}
+
+impl<T, M> BatchTransport for Layered<T, M>
+where
+    T: BatchTransport,
+    T::Out: 'static + Send,
+    T::Batch: 'static + Send,
+    M: Middleware + Send + 'static,
+{
+    type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let middleware = self.middleware.clone();
+        let requests = requests
+            .into_iter()
+            .map(|(id, call)| {
+                let call = middleware.on_request(id, call);
+                let intercepted = middleware.intercept(id, &call);
+                (id, call, intercepted)
+            })
+            .collect::<Vec<_>>();
+        let pending = requests
+            .iter()
+            .filter(|(_, _, intercepted)| intercepted.is_none())
+            .map(|(id, call, _)| (*id, call.clone()))
+            .collect::<Vec<_>>();
+        let batch = if pending.is_empty() {
+            future::ready(Ok(vec![])).boxed()
+        } else {
+            self.transport.send_batch(pending).boxed()
+        };
+        async move {
+            let mut answers = batch.await?.into_iter();
+            requests
+                .into_iter()
+                .map(|(id, call, intercepted)| {
+                    let result = match intercepted {
+                        Some(result) => result,
+                        None => answers
+                            .next()
+                            .unwrap_or_else(|| Err(Error::InvalidResponse("unexpected number of responses".into()))),
+                    };
+                    Ok(middleware.on_response(id, &call, result))
+                })
+                .collect()
+        }
+        .boxed()
+    }
+}
+
+fn method(call: &rpc::Call) -> &str {
+    match call {
+        rpc::Call::MethodCall(call) => &call.method,
+        rpc::Call::Notification(call) => &call.method,
+        rpc::Call::Invalid { .. } => "",
+    }
+}
+
+/// Logs every call and its result to a sink, e.g. `|line| ic_cdk::println!("{}", line)`.
+#[derive(Debug, Clone)]
+pub struct Logger {
+    sink: fn(&str),
+}
+
+impl Logger {
+    /// Create new logger writing to `sink`.
+    pub fn new(sink: fn(&str)) -> Self {
+        Logger { sink }
+    }
+}
+
+impl Middleware for Logger {
+    fn on_request(&self, id: RequestId, call: rpc::Call) -> rpc::Call {
+        if let Ok(request) = serde_json::to_string(&call) {
+            (self.sink)(&format!("[id:{}] sending request: {}", id, request));
+        }
+        call
+    }
+
+    fn on_response(
+        &self,
+        id: RequestId,
+        call: &rpc::Call,
+        result: error::Result<rpc::Value>,
+    ) -> error::Result<rpc::Value> {
+        match &result {
+            Ok(value) => (self.sink)(&format!("[id:{}] {} returned: {}", id, method(call), value)),
+            Err(err) => (self.sink)(&format!("[id:{}] {} failed: {}", id, method(call), err)),
+        }
+        result
+    }
+}
+
+/// Refuses calls to methods which are not allowed, without sending them.
+#[derive(Debug, Clone)]
+pub struct AllowList {
+    methods: Arc<HashSet<String>>,
+}
+
+impl AllowList {
+    /// Create new allow-list of the given methods.
+    pub fn new(methods: &[&str]) -> Self {
+        AllowList {
+            methods: Arc::new(methods.iter().map(|m| m.to_string()).collect()),
+        }
+    }
+}
+
+impl Middleware for AllowList {
+    fn intercept(&self, _id: RequestId, call: &rpc::Call) -> Option<error::Result<rpc::Value>> {
+        let method = method(call);
+        if self.methods.contains(method) {
+            None
+        } else {
+            Some(Err(Error::Transport(TransportError::Message(format!(
+                "method {} is not allowed",
+                method
+            )))))
+        }
+    }
+}
+
+/// Calls and failures of a method.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MethodStats {
+    pub calls: u64,
+    pub errors: u64,
+}
+
+/// Counts calls and failures per method, shared by all clones.
+#[derive(Debug, Clone, Default)]
+pub struct CallCounter {
+    stats: Arc<Mutex<BTreeMap<String, MethodStats>>>,
+}
+
+impl CallCounter {
+    /// Stats of every method called so far.
+    pub fn stats(&self) -> BTreeMap<String, MethodStats> {
+        self.stats.lock().clone()
+    }
+}
+
+impl Middleware for CallCounter {
+    fn on_response(
+        &self,
+        _id: RequestId,
+        call: &rpc::Call,
+        result: error::Result<rpc::Value>,
+    ) -> error::Result<rpc::Value> {
+        let mut stats = self.stats.lock();
+        let stats = stats.entry(method(call).to_string()).or_default();
+        stats.calls += 1;
+        if result.is_err() {
+            stats.errors += 1;
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transports::test::TestTransport;
+    use futures::executor::block_on;
+
+    /// Rewrites `latest` block tags to `finalized`.
+    #[derive(Debug, Clone)]
+    struct Finalized;
+
+    impl Middleware for Finalized {
+        fn on_request(&self, _id: RequestId, call: rpc::Call) -> rpc::Call {
+            match call {
+                rpc::Call::MethodCall(mut call) => {
+                    if let rpc::Params::Array(ref mut params) = call.params {
+                        params
+                            .iter_mut()
+                            .filter(|p| p.as_str() == Some("latest"))
+                            .for_each(|p| *p = "finalized".into());
+                    }
+                    rpc::Call::MethodCall(call)
+                }
+                call => call,
+            }
+        }
+    }
+
+    #[test]
+    fn should_refuse_methods_not_allowed() {
+        let mut inner = TestTransport::default();
+        inner.add_response(rpc::Value::String("0x1".into()));
+        let transport = Layered::new(inner.clone(), AllowList::new(&["eth_chainId"]));
+
+        assert_eq!(block_on(transport.execute("eth_chainId", vec![])), Ok("0x1".into()));
+        assert!(block_on(transport.execute("eth_sendRawTransaction", vec![])).is_err());
+        inner.assert_request("eth_chainId", &[]);
+        inner.assert_no_more_requests();
+    }
+
+    #[test]
+    fn should_count_calls_through_nested_layers() {
+        let mut inner = TestTransport::default();
+        inner.add_response(rpc::Value::String("0x1".into()));
+        inner.add_response(rpc::Value::String("0x2".into()));
+        let counter = CallCounter::default();
+        let transport = Layered::new(Layered::new(inner, AllowList::new(&["eth_blockNumber"])), counter.clone());
+
+        block_on(transport.execute("eth_blockNumber", vec![])).unwrap();
+        block_on(transport.execute("eth_blockNumber", vec![])).unwrap();
+        block_on(transport.execute("eth_accounts", vec![])).unwrap_err();
+
+        let stats = counter.stats();
+        assert_eq!(stats["eth_blockNumber"], MethodStats { calls: 2, errors: 0 });
+        assert_eq!(stats["eth_accounts"], MethodStats { calls: 1, errors: 1 });
+    }
+
+    /// Answers every call with its params.
+    #[derive(Debug, Clone, Default)]
+    struct Echo;
+
+    impl Transport for Echo {
+        type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+            (1, crate::helpers::build_request(1, method, params))
+        }
+
+        fn send(&self, _id: RequestId, request: rpc::Call) -> Self::Out {
+            let params = match request {
+                rpc::Call::MethodCall(call) => serde_json::to_value(call.params).unwrap(),
+                _ => rpc::Value::Null,
+            };
+            future::ready(Ok(params)).boxed()
+        }
+    }
+
+    #[test]
+    fn should_rewrite_calls() {
+        let transport = Layered::new(Echo, Finalized);
+        let params = block_on(transport.execute("eth_getBalance", vec!["0x00".into(), "latest".into()]));
+        assert_eq!(params, Ok(serde_json::json!(["0x00", "finalized"])));
+    }
+}
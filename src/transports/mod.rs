@@ -6,15 +6,33 @@ pub use self::batch::Batch;
 pub mod either;
 pub use self::either::Either;
 
-#[cfg(any(feature = "http", feature = "http-rustls"))]
+#[cfg(feature = "http")]
 pub mod http;
-#[cfg(any(feature = "http", feature = "http-rustls"))]
+#[cfg(feature = "http")]
 pub use self::http::Http;
 
 pub mod ic_http_client;
 pub use self::ic_http_client::ICHttpClient;
 pub mod ic_http;
 pub use self::ic_http::ICHttp;
+pub mod ic_http_pool;
+pub use self::ic_http_pool::ICHttpPool;
+pub mod ic_proxy;
+pub use self::ic_proxy::ICProxy;
+pub mod evm_rpc;
+pub use self::evm_rpc::EvmRpc;
+pub mod consensus;
+pub use self::consensus::Consensus;
+pub mod response_size;
+pub use self::response_size::ResponseSizePolicy;
+pub mod retry;
+pub use self::retry::{Retry, RetryPolicy};
+pub mod middleware;
+pub use self::middleware::{Layered, Middleware};
+pub mod cache;
+pub use self::cache::Cache;
+pub mod polling;
+pub use self::polling::Polling;
 
 #[cfg(any(feature = "ws-tokio", feature = "ws-async-std"))]
 pub mod ws;
@@ -29,6 +47,11 @@ pub use self::ipc::Ipc;
 #[cfg(any(feature = "test", test))]
 pub mod test;
 
+#[cfg(feature = "replay")]
+pub mod replay;
+#[cfg(feature = "replay")]
+pub use self::replay::ReplayTransport;
+
 #[cfg(feature = "url")]
 impl From<url::ParseError> for crate::Error {
     fn from(err: url::ParseError) -> Self {
@@ -0,0 +1,412 @@
+//! Polling emulation of `eth_subscribe`
+//!
+//! Canisters can only make request/response outcalls. [Polling] answers `eth_subscribe` itself
+//! and feeds the subscriptions from `eth_blockNumber`, `eth_getBlockByNumber` and `eth_getLogs`
+//! calls made on an IC timer, so subscription code runs unchanged on top of any transport:
+//!
+//! ```ignore
+//! let web3 = Web3::new(Polling::new(ICHttp::new(URL, None)?, Duration::from_secs(12)));
+//! let mut heads = web3.eth_subscribe().subscribe_new_heads().await?;
+//! while let Some(head) = heads.next().await { .. }
+//! ```
+//!
+//! Only `newHeads` and `logs` subscriptions are supported.
+
+use crate::{
+    api::SubscriptionId,
+    error::{self, Error, TransportError},
+    helpers, rpc,
+    types::U64,
+    DuplexTransport, RequestId, Transport,
+};
+use futures::{
+    channel::mpsc,
+    future::{self, BoxFuture, FutureExt},
+};
+use parking_lot::Mutex;
+use std::{collections::BTreeMap, sync::Arc, time::Duration};
+
+/// Most blocks fetched by a `newHeads` subscription in one poll, the rest follow on the next ones.
+const MAX_BLOCKS_PER_POLL: u64 = 32;
+/// Widest block range of the `eth_getLogs` call of a `logs` subscription in one poll, the rest
+/// follow on the next ones. Providers refuse much wider ranges.
+const MAX_LOG_BLOCKS: u64 = 1_000;
+
+#[derive(Debug, Clone)]
+enum Kind {
+    NewHeads,
+    Logs(rpc::Value),
+}
+
+impl Kind {
+    fn from_params(params: &[rpc::Value]) -> error::Result<Self> {
+        match params.first().and_then(rpc::Value::as_str) {
+            Some("newHeads") => Ok(Kind::NewHeads),
+            Some("logs") => match params.get(1).cloned().unwrap_or_else(|| rpc::Value::Object(Default::default())) {
+                filter @ rpc::Value::Object(_) if filter.get("blockHash").is_none() => Ok(Kind::Logs(filter)),
+                _ => Err(unsupported("logs with a block hash filter")),
+            },
+            Some(kind) => Err(unsupported(kind)),
+            None => Err(Error::Decoder("missing subscription kind".into())),
+        }
+    }
+}
+
+fn unsupported(kind: &str) -> Error {
+    Error::Transport(TransportError::Message(format!(
+        "subscription {} is not supported by polling",
+        kind
+    )))
+}
+
+#[derive(Debug)]
+struct Subscription {
+    kind: Kind,
+    next_block: u64,
+    busy: bool,
+    sender: mpsc::UnboundedSender<rpc::Value>,
+    receiver: Option<mpsc::UnboundedReceiver<rpc::Value>>,
+    timer: Option<ic_cdk_timers::TimerId>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    counter: u64,
+    subscriptions: BTreeMap<SubscriptionId, Subscription>,
+}
+
+impl State {
+    fn add(&mut self, kind: Kind, next_block: u64) -> String {
+        self.counter += 1;
+        let id = format!("0x{:x}", self.counter);
+        let (sender, receiver) = mpsc::unbounded();
+        self.subscriptions.insert(
+            SubscriptionId::from(id.clone()),
+            Subscription {
+                kind,
+                next_block,
+                busy: false,
+                sender,
+                receiver: Some(receiver),
+                timer: None,
+            },
+        );
+        id
+    }
+
+    fn remove(&mut self, id: &SubscriptionId) -> bool {
+        match self.subscriptions.remove(id) {
+            Some(subscription) => {
+                if let Some(timer) = subscription.timer {
+                    ic_cdk_timers::clear_timer(timer);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+/// Transport emulating `newHeads` and `logs` subscriptions by polling another transport.
+///
+/// Every subscription polls on its own IC timer, starting from the block after the latest one
+/// at the time of subscribing. Failed polls are retried on the next tick. All other calls go
+/// to the wrapped transport.
+#[derive(Debug, Clone)]
+pub struct Polling<T> {
+    transport: T,
+    interval: Duration,
+    state: Arc<Mutex<State>>,
+}
+
+impl<T: Transport> Polling<T> {
+    /// Wrap `transport`, polling every `interval`, e.g. the block time of the chain.
+    pub fn new(transport: T, interval: Duration) -> Self {
+        Polling {
+            transport,
+            interval,
+            state: Default::default(),
+        }
+    }
+
+    /// Polls every subscription once, e.g. from a timer of the canister itself. Returns the
+    /// first failure, after polling the other subscriptions.
+    pub async fn poll(&self) -> error::Result<()> {
+        let ids = self.state.lock().subscriptions.keys().cloned().collect::<Vec<_>>();
+        let mut result = Ok(());
+        for id in ids {
+            let polled = self.poll_subscription(&id).await;
+            if result.is_ok() {
+                result = polled;
+            }
+        }
+        result
+    }
+
+    async fn poll_subscription(&self, id: &SubscriptionId) -> error::Result<()> {
+        let (kind, from) = match self.state.lock().subscriptions.get_mut(id) {
+            Some(subscription) if !subscription.busy => {
+                subscription.busy = true;
+                (subscription.kind.clone(), subscription.next_block)
+            }
+            _ => return Ok(()),
+        };
+        let fetched = self.fetch(&kind, from).await;
+
+        let mut state = self.state.lock();
+        // unsubscribed in the meantime
+        let subscription = match state.subscriptions.get_mut(id) {
+            Some(subscription) => subscription,
+            None => return Ok(()),
+        };
+        subscription.busy = false;
+        let (items, next_block) = fetched?;
+        subscription.next_block = next_block;
+        for item in items {
+            // the stream may be gone already, it unsubscribes on drop
+            let _ = subscription.sender.unbounded_send(item);
+        }
+        Ok(())
+    }
+
+    /// New notifications from block `from` on, and the block to continue from.
+    async fn fetch(&self, kind: &Kind, from: u64) -> error::Result<(Vec<rpc::Value>, u64)> {
+        let latest = helpers::decode::<U64>(self.transport.execute("eth_blockNumber", vec![]).await?)?.as_u64();
+        if latest < from {
+            return Ok((vec![], from));
+        }
+        match kind {
+            Kind::NewHeads => {
+                let to = latest.min(from + MAX_BLOCKS_PER_POLL - 1);
+                let mut heads = vec![];
+                for number in from..=to {
+                    let block = self
+                        .transport
+                        .execute("eth_getBlockByNumber", vec![quantity(number), false.into()])
+                        .await?;
+                    // the provider is not there yet
+                    if block.is_null() {
+                        return Ok((heads, number));
+                    }
+                    heads.push(block);
+                }
+                Ok((heads, to + 1))
+            }
+            Kind::Logs(filter) => {
+                let to = latest.min(from + MAX_LOG_BLOCKS - 1);
+                let mut filter = filter.clone();
+                filter["fromBlock"] = quantity(from);
+                filter["toBlock"] = quantity(to);
+                match self.transport.execute("eth_getLogs", vec![filter]).await? {
+                    rpc::Value::Array(logs) => Ok((logs, to + 1)),
+                    other => Err(Error::InvalidResponse(format!("expected logs, got: {}", other))),
+                }
+            }
+        }
+    }
+
+    fn stream(&self, id: &SubscriptionId) -> error::Result<mpsc::UnboundedReceiver<rpc::Value>> {
+        self.state
+            .lock()
+            .subscriptions
+            .get_mut(id)
+            .and_then(|subscription| subscription.receiver.take())
+            .ok_or_else(|| Error::Transport(TransportError::Message(format!("unknown subscription {:?}", id))))
+    }
+}
+
+fn quantity(number: u64) -> rpc::Value {
+    helpers::serialize(&U64::from(number))
+}
+
+fn params(call: &rpc::Call) -> Vec<rpc::Value> {
+    match call {
+        rpc::Call::MethodCall(rpc::MethodCall {
+            params: rpc::Params::Array(params),
+            ..
+        }) => params.clone(),
+        _ => vec![],
+    }
+}
+
+fn method(call: &rpc::Call) -> &str {
+    match call {
+        rpc::Call::MethodCall(call) => &call.method,
+        _ => "",
+    }
+}
+
+impl<T> Transport for Polling<T>
+where
+    T: Transport,
+    T::Out: 'static + Send,
+{
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        match method(&request) {
+            "eth_subscribe" => {
+                let kind = match Kind::from_params(&params(&request)) {
+                    Ok(kind) => kind,
+                    Err(err) => return future::ready(Err(err)).boxed(),
+                };
+                let latest = self.transport.execute("eth_blockNumber", vec![]);
+                let state = self.state.clone();
+                async move {
+                    let latest = helpers::decode::<U64>(latest.await?)?.as_u64();
+                    Ok(state.lock().add(kind, latest + 1).into())
+                }
+                .boxed()
+            }
+            "eth_unsubscribe" => {
+                let removed = match params(&request).first().and_then(rpc::Value::as_str) {
+                    Some(id) => self.state.lock().remove(&id.to_string().into()),
+                    None => false,
+                };
+                future::ready(Ok(removed.into())).boxed()
+            }
+            _ => self.transport.send(id, request).boxed(),
+        }
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.transport.set_max_response_bytes(v);
+    }
+}
+
+impl<T> DuplexTransport for Polling<T>
+where
+    T: Transport + 'static,
+    T::Out: 'static + Send,
+{
+    type NotificationStream = mpsc::UnboundedReceiver<rpc::Value>;
+
+    fn subscribe(&self, id: SubscriptionId) -> error::Result<Self::NotificationStream> {
+        let stream = self.stream(&id)?;
+        let this = self.clone();
+        let poll_id = id.clone();
+        let timer = ic_cdk_timers::set_timer_interval(self.interval, move || {
+            let (this, id) = (this.clone(), poll_id.clone());
+            ic_cdk::spawn(async move {
+                let _ = this.poll_subscription(&id).await;
+            });
+        });
+        match self.state.lock().subscriptions.get_mut(&id) {
+            Some(subscription) => subscription.timer = Some(timer),
+            None => ic_cdk_timers::clear_timer(timer),
+        }
+        Ok(stream)
+    }
+
+    fn unsubscribe(&self, id: SubscriptionId) -> error::Result<()> {
+        self.state.lock().remove(&id);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transports::test::TestTransport;
+    use futures::{executor::block_on, StreamExt};
+    use serde_json::json;
+
+    fn subscribe(polling: &Polling<TestTransport>, params: Vec<rpc::Value>) -> mpsc::UnboundedReceiver<rpc::Value> {
+        let id = block_on(polling.execute("eth_subscribe", params)).unwrap();
+        polling.stream(&id.as_str().unwrap().to_string().into()).unwrap()
+    }
+
+    #[test]
+    fn should_emulate_new_heads() {
+        let mut inner = TestTransport::default();
+        let polling = Polling::new(inner.clone(), Duration::from_secs(12));
+        inner.add_response(json!("0x10"));
+        let mut heads = subscribe(&polling, vec!["newHeads".into()]);
+
+        inner.add_response(json!("0x12"));
+        inner.add_response(json!({"number": "0x11"}));
+        inner.add_response(json!({"number": "0x12"}));
+        block_on(polling.poll()).unwrap();
+        inner.add_response(json!("0x12"));
+        block_on(polling.poll()).unwrap();
+
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request("eth_getBlockByNumber", &[r#""0x11""#.into(), "false".into()]);
+        inner.assert_request("eth_getBlockByNumber", &[r#""0x12""#.into(), "false".into()]);
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_no_more_requests();
+        assert_eq!(block_on(heads.next()), Some(json!({"number": "0x11"})));
+        assert_eq!(block_on(heads.next()), Some(json!({"number": "0x12"})));
+        assert!(heads.try_next().is_err());
+    }
+
+    #[test]
+    fn should_emulate_logs() {
+        let mut inner = TestTransport::default();
+        let polling = Polling::new(inner.clone(), Duration::from_secs(12));
+        inner.add_response(json!("0x10"));
+        let filter = json!({"address": "0x0000000000000000000000000000000000000001"});
+        let mut logs = subscribe(&polling, vec!["logs".into(), filter]);
+
+        inner.add_response(json!("0x12"));
+        inner.add_response(json!([{"logIndex": "0x0"}, {"logIndex": "0x1"}]));
+        block_on(polling.poll()).unwrap();
+
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request(
+            "eth_getLogs",
+            &[r#"{"address":"0x0000000000000000000000000000000000000001","fromBlock":"0x11","toBlock":"0x12"}"#.into()],
+        );
+        assert_eq!(block_on(logs.next()), Some(json!({"logIndex": "0x0"})));
+        assert_eq!(block_on(logs.next()), Some(json!({"logIndex": "0x1"})));
+    }
+
+    #[test]
+    fn should_catch_up_on_logs_in_bounded_ranges() {
+        let mut inner = TestTransport::default();
+        let polling = Polling::new(inner.clone(), Duration::from_secs(12));
+        inner.add_response(json!("0x0"));
+        let _logs = subscribe(&polling, vec!["logs".into()]);
+
+        inner.add_response(json!("0x100000"));
+        inner.add_response(json!([]));
+        block_on(polling.poll()).unwrap();
+        inner.add_response(json!("0x100000"));
+        inner.add_response(json!([]));
+        block_on(polling.poll()).unwrap();
+
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request("eth_getLogs", &[r#"{"fromBlock":"0x1","toBlock":"0x3e8"}"#.into()]);
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_request("eth_getLogs", &[r#"{"fromBlock":"0x3e9","toBlock":"0x7d0"}"#.into()]);
+        inner.assert_no_more_requests();
+    }
+
+    #[test]
+    fn should_unsubscribe() {
+        let mut inner = TestTransport::default();
+        let polling = Polling::new(inner.clone(), Duration::from_secs(12));
+        inner.add_response(json!("0x10"));
+        let id = block_on(polling.execute("eth_subscribe", vec!["newHeads".into()])).unwrap();
+
+        assert_eq!(block_on(polling.execute("eth_unsubscribe", vec![id.clone()])), Ok(true.into()));
+        assert_eq!(block_on(polling.execute("eth_unsubscribe", vec![id])), Ok(false.into()));
+        block_on(polling.poll()).unwrap();
+        inner.assert_request("eth_blockNumber", &[]);
+        inner.assert_no_more_requests();
+    }
+
+    #[test]
+    fn should_refuse_unsupported_subscriptions() {
+        let polling = Polling::new(TestTransport::default(), Duration::from_secs(12));
+        let err = block_on(polling.execute("eth_subscribe", vec!["syncing".into()])).unwrap_err();
+        assert_eq!(err, unsupported("syncing"));
+    }
+}
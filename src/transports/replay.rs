@@ -0,0 +1,385 @@
+//! Record/replay transport for offline tests
+//!
+//! Record the calls of a test once against a real provider:
+//!
+//! ```ignore
+//! let transport = ReplayTransport::record(ICHttp::new(URL, None)?, "tests/fixtures/balance.json");
+//! ```
+//!
+//! and replay them from the fixture afterwards, without network access:
+//!
+//! ```ignore
+//! let transport = ReplayTransport::replay("tests/fixtures/balance.json")?;
+//! let web3 = Web3::new(transport);
+//! ```
+
+use crate::{
+    error::{self, Error, TransportError},
+    helpers, rpc, BatchTransport, RequestId, Transport,
+};
+use futures::future::{self, BoxFuture, FutureExt};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use std::{
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+/// Recorded answer to a call.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reply {
+    /// The call succeeded.
+    Result(rpc::Value),
+    /// The provider answered with a JSON-RPC error.
+    Error(rpc::Error),
+    /// The call failed before reaching the provider, e.g. a transport error.
+    Failure(String),
+}
+
+impl From<&error::Result<rpc::Value>> for Reply {
+    fn from(result: &error::Result<rpc::Value>) -> Self {
+        match result {
+            Ok(value) => Reply::Result(value.clone()),
+            Err(Error::Rpc(err)) => Reply::Error(err.clone()),
+            Err(err) => Reply::Failure(err.to_string()),
+        }
+    }
+}
+
+impl From<Reply> for error::Result<rpc::Value> {
+    fn from(reply: Reply) -> Self {
+        match reply {
+            Reply::Result(value) => Ok(value),
+            Reply::Error(err) => Err(Error::Rpc(err)),
+            Reply::Failure(message) => Err(Error::Transport(TransportError::Message(message))),
+        }
+    }
+}
+
+/// Request/response pair of a fixture.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub method: String,
+    pub params: Vec<rpc::Value>,
+    pub reply: Reply,
+}
+
+impl Entry {
+    fn of(call: &rpc::Call) -> (String, Vec<rpc::Value>) {
+        match call {
+            rpc::Call::MethodCall(call) => (call.method.clone(), params(&call.params)),
+            rpc::Call::Notification(call) => (call.method.clone(), params(&call.params)),
+            rpc::Call::Invalid { .. } => (String::new(), vec![]),
+        }
+    }
+}
+
+fn params(params: &rpc::Params) -> Vec<rpc::Value> {
+    match params {
+        rpc::Params::Array(params) => params.clone(),
+        rpc::Params::Map(map) => vec![rpc::Value::Object(map.clone())],
+        rpc::Params::None => vec![],
+    }
+}
+
+/// Transport without a provider, failing every call. Stands in for the recorded transport
+/// when replaying.
+#[derive(Debug, Clone, Default)]
+pub struct Offline {
+    id: Arc<AtomicUsize>,
+}
+
+impl Transport for Offline {
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        let id = self.id.fetch_add(1, Ordering::AcqRel);
+        (id, helpers::build_request(id, method, params))
+    }
+
+    fn send(&self, _id: RequestId, _request: rpc::Call) -> Self::Out {
+        future::ready(Err(Error::Unreachable)).boxed()
+    }
+}
+
+impl BatchTransport for Offline {
+    type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+    fn send_batch<T>(&self, _requests: T) -> Self::Batch
+    where
+        T: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        future::ready(Err(Error::Unreachable)).boxed()
+    }
+}
+
+#[derive(Debug)]
+enum Mode {
+    Record,
+    Replay { next: usize },
+}
+
+#[derive(Debug)]
+struct State {
+    mode: Mode,
+    path: PathBuf,
+    entries: Vec<Entry>,
+}
+
+impl State {
+    fn record(&mut self, call: &rpc::Call, result: &error::Result<rpc::Value>) -> error::Result<()> {
+        let (method, params) = Entry::of(call);
+        self.entries.push(Entry {
+            method,
+            params,
+            reply: result.into(),
+        });
+        std::fs::write(&self.path, serde_json::to_string_pretty(&self.entries)?)?;
+        Ok(())
+    }
+
+    fn replay(&mut self, call: &rpc::Call) -> error::Result<rpc::Value> {
+        let next = match self.mode {
+            Mode::Replay { ref mut next } => next,
+            Mode::Record => return Err(Error::Internal),
+        };
+        let (method, params) = Entry::of(call);
+        let entry = self.entries.get(*next).ok_or_else(|| {
+            mismatch(format!(
+                "replay fixture {} has no request #{}\n+ actual: {} {}",
+                self.path.display(),
+                next,
+                method,
+                rpc::Value::Array(params.clone())
+            ))
+        })?;
+        if entry.method != method || entry.params != params {
+            return Err(mismatch(diff(*next, entry, &method, &params)));
+        }
+        *next += 1;
+        entry.reply.clone().into()
+    }
+}
+
+fn mismatch(message: String) -> Error {
+    Error::Transport(TransportError::Message(message))
+}
+
+/// Describes how a request differs from the recorded one, parameter by parameter.
+fn diff(idx: usize, expected: &Entry, method: &str, params: &[rpc::Value]) -> String {
+    let mut lines = vec![format!("replay mismatch at request #{}", idx)];
+    if expected.method != method {
+        lines.push(format!("- method: {}", expected.method));
+        lines.push(format!("+ method: {}", method));
+    }
+    for i in 0..expected.params.len().max(params.len()) {
+        let (a, b) = (expected.params.get(i), params.get(i));
+        if a != b {
+            let show = |p: Option<&rpc::Value>| p.map(ToString::to_string).unwrap_or_else(|| "<none>".into());
+            lines.push(format!("- params[{}]: {}", i, show(a)));
+            lines.push(format!("+ params[{}]: {}", i, show(b)));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Transport recording request/response pairs to a JSON fixture, or serving them back.
+///
+/// In record mode every call goes to the wrapped transport and the fixture is rewritten after
+/// each answer. In replay mode the calls have to come in the recorded order, with the recorded
+/// method and parameters; any other call fails with a diff against the expected request.
+/// Request ids are not compared.
+#[derive(Debug, Clone)]
+pub struct ReplayTransport<T> {
+    transport: T,
+    state: Arc<Mutex<State>>,
+}
+
+impl<T: Transport> ReplayTransport<T> {
+    /// Record the calls made through `transport` to the fixture at `path`.
+    pub fn record(transport: T, path: impl AsRef<Path>) -> Self {
+        ReplayTransport {
+            transport,
+            state: Arc::new(Mutex::new(State {
+                mode: Mode::Record,
+                path: path.as_ref().to_path_buf(),
+                entries: vec![],
+            })),
+        }
+    }
+}
+
+impl ReplayTransport<Offline> {
+    /// Serve the calls recorded in the fixture at `path`.
+    pub fn replay(path: impl AsRef<Path>) -> error::Result<Self> {
+        let entries = serde_json::from_slice(&std::fs::read(path.as_ref())?)?;
+        Ok(ReplayTransport {
+            transport: Offline::default(),
+            state: Arc::new(Mutex::new(State {
+                mode: Mode::Replay { next: 0 },
+                path: path.as_ref().to_path_buf(),
+                entries,
+            })),
+        })
+    }
+
+    /// Asserts every recorded request was replayed.
+    pub fn assert_replayed(&self) {
+        let state = self.state.lock();
+        if let Mode::Replay { next } = state.mode {
+            assert_eq!(
+                next,
+                state.entries.len(),
+                "Expected more requests, next is: {:?}",
+                state.entries.get(next)
+            );
+        }
+    }
+}
+
+impl<T> ReplayTransport<T> {
+    /// Recorded or replayed entries.
+    pub fn entries(&self) -> Vec<Entry> {
+        self.state.lock().entries.clone()
+    }
+
+    fn is_recording(&self) -> bool {
+        matches!(self.state.lock().mode, Mode::Record)
+    }
+}
+
+impl<T> Transport for ReplayTransport<T>
+where
+    T: Transport,
+    T::Out: 'static + Send,
+{
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        if !self.is_recording() {
+            return future::ready(self.state.lock().replay(&request)).boxed();
+        }
+        let state = self.state.clone();
+        let answer = self.transport.send(id, request.clone());
+        async move {
+            let result = answer.await;
+            state.lock().record(&request, &result)?;
+            result
+        }
+        .boxed()
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.transport.set_max_response_bytes(v);
+    }
+}
+
+impl<T> BatchTransport for ReplayTransport<T>
+where
+    T: BatchTransport,
+    T::Out: 'static + Send,
+    T::Batch: 'static + Send,
+{
+    type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let requests = requests.into_iter().collect::<Vec<_>>();
+        if !self.is_recording() {
+            let mut state = self.state.lock();
+            let results = requests.iter().map(|(_, call)| state.replay(call)).collect();
+            return future::ready(Ok(results)).boxed();
+        }
+        let state = self.state.clone();
+        let answers = self.transport.send_batch(requests.clone());
+        async move {
+            let results = answers.await?;
+            let mut state = state.lock();
+            for ((_, call), result) in requests.iter().zip(results.iter()) {
+                state.record(call, result)?;
+            }
+            Ok(results)
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transports::test::TestTransport;
+
+    fn fixture(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ic-web3-replay-{}-{}.json", name, std::process::id()))
+    }
+
+    fn record(path: &Path) {
+        let mut inner = TestTransport::default();
+        inner.add_response(rpc::Value::String("0x10".into()));
+        inner.add_response(rpc::Value::String("0x1".into()));
+        let transport = ReplayTransport::record(inner, path);
+        futures::executor::block_on(async {
+            assert_eq!(transport.execute("eth_blockNumber", vec![]).await, Ok("0x10".into()));
+            assert_eq!(
+                transport.execute("eth_getBalance", vec!["0x00".into(), "latest".into()]).await,
+                Ok("0x1".into())
+            );
+        });
+    }
+
+    #[test]
+    fn should_replay_recorded_calls() {
+        let path = fixture("replay");
+        record(&path);
+
+        let transport = ReplayTransport::replay(&path).unwrap();
+        futures::executor::block_on(async {
+            assert_eq!(transport.execute("eth_blockNumber", vec![]).await, Ok("0x10".into()));
+            assert_eq!(
+                transport.execute("eth_getBalance", vec!["0x00".into(), "latest".into()]).await,
+                Ok("0x1".into())
+            );
+        });
+        transport.assert_replayed();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_diff_mismatched_calls() {
+        let path = fixture("mismatch");
+        record(&path);
+
+        let transport = ReplayTransport::replay(&path).unwrap();
+        futures::executor::block_on(async {
+            transport.execute("eth_blockNumber", vec![]).await.unwrap();
+            let err = transport
+                .execute("eth_getBalance", vec!["0x00".into(), "pending".into()])
+                .await
+                .unwrap_err();
+            assert_eq!(
+                err,
+                mismatch(
+                    "replay mismatch at request #1\n- params[1]: \"latest\"\n+ params[1]: \"pending\"".into()
+                )
+            );
+        });
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_replay_errors() {
+        let err = rpc::Error::invalid_params("bad block");
+        let reply = Reply::from(&Err(Error::Rpc(err.clone())));
+        assert_eq!(error::Result::from(reply), Err(Error::Rpc(err)));
+    }
+}
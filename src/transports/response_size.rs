@@ -0,0 +1,175 @@
+//! Response size limits of IC http outcalls
+
+use crate::{
+    error::{Error, TransportError},
+    rpc,
+};
+use ic_cdk::api::call::RejectionCode;
+use std::collections::HashMap;
+
+/// Limit for calls answering a single scalar, e.g. `eth_blockNumber`. It leaves room for the
+/// response headers, which count towards the limit as well.
+const SCALAR_BYTES: u64 = 8_192;
+/// Limit for methods without a known size.
+const DEFAULT_BYTES: u64 = 500_000;
+/// Largest limit accepted by the IC.
+const CAP_BYTES: u64 = 2_000_000;
+
+/// Stock limits of common methods.
+const METHOD_BYTES: &[(&str, u64)] = &[
+    ("eth_blockNumber", SCALAR_BYTES),
+    ("eth_chainId", SCALAR_BYTES),
+    ("net_version", SCALAR_BYTES),
+    ("eth_gasPrice", SCALAR_BYTES),
+    ("eth_maxPriorityFeePerGas", SCALAR_BYTES),
+    ("eth_getBalance", SCALAR_BYTES),
+    ("eth_getTransactionCount", SCALAR_BYTES),
+    ("eth_estimateGas", SCALAR_BYTES),
+    ("eth_sendRawTransaction", SCALAR_BYTES),
+    ("eth_feeHistory", 16_384),
+    ("eth_getTransactionByHash", 50_000),
+    ("eth_getTransactionReceipt", 100_000),
+    ("eth_call", 100_000),
+    ("eth_getCode", 100_000),
+    ("eth_getBlockByNumber", 100_000),
+    ("eth_getBlockByHash", 100_000),
+    ("eth_getLogs", 1_000_000),
+];
+
+/// Per-method `max_response_bytes` of outcalls.
+///
+/// Every byte of the limit is paid for whether the provider uses it or not, so small answers
+/// get small limits. Blocks with full transactions always get the cap.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResponseSizePolicy {
+    default: u64,
+    cap: u64,
+    methods: HashMap<String, u64>,
+}
+
+impl Default for ResponseSizePolicy {
+    fn default() -> Self {
+        let mut policy = ResponseSizePolicy::new(DEFAULT_BYTES, CAP_BYTES);
+        for (method, bytes) in METHOD_BYTES {
+            policy.set_method(method, *bytes);
+        }
+        policy
+    }
+}
+
+impl ResponseSizePolicy {
+    /// Create a policy without method limits, using `default` for every call and never
+    /// growing a limit beyond `cap`.
+    pub fn new(default: u64, cap: u64) -> Self {
+        ResponseSizePolicy {
+            default: default.min(cap),
+            cap,
+            methods: HashMap::new(),
+        }
+    }
+
+    /// Set the limit of a method.
+    pub fn set_method(&mut self, method: &str, bytes: u64) {
+        self.methods.insert(method.to_string(), bytes.min(self.cap));
+    }
+
+    /// Largest limit of this policy.
+    pub fn cap(&self) -> u64 {
+        self.cap
+    }
+
+    /// Limit of a single call.
+    pub fn for_call(&self, call: &rpc::Call) -> u64 {
+        let call = match call {
+            rpc::Call::MethodCall(call) => call,
+            _ => return self.default,
+        };
+        let full_transactions = match &call.params {
+            rpc::Params::Array(params) => params.get(1) == Some(&rpc::Value::Bool(true)),
+            _ => false,
+        };
+        match call.method.as_str() {
+            "eth_getBlockByNumber" | "eth_getBlockByHash" if full_transactions => self.cap,
+            method => self.methods.get(method).copied().unwrap_or(self.default),
+        }
+    }
+
+    /// Limit of a request, the sum of the limits of a batch.
+    pub fn for_request(&self, request: &rpc::Request) -> u64 {
+        match request {
+            rpc::Request::Single(call) => self.for_call(call),
+            rpc::Request::Batch(calls) => calls.iter().map(|call| self.for_call(call)).sum::<u64>().min(self.cap),
+        }
+    }
+
+    /// Next limit to retry an oversize response with, `None` once the cap is reached.
+    pub fn grow(&self, bytes: u64) -> Option<u64> {
+        if bytes >= self.cap {
+            None
+        } else {
+            Some(bytes.saturating_mul(2).min(self.cap))
+        }
+    }
+}
+
+/// Whether the outcall was rejected because the response exceeded `max_response_bytes`.
+pub fn is_oversize(err: &Error) -> bool {
+    match err {
+        Error::Transport(TransportError::Rejected(RejectionCode::SysFatal, message)) => {
+            message.to_lowercase().contains("size limit")
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helpers::build_request;
+
+    #[test]
+    fn should_pick_limit_by_method() {
+        let policy = ResponseSizePolicy::default();
+        assert_eq!(policy.for_call(&build_request(1, "eth_blockNumber", vec![])), SCALAR_BYTES);
+        assert_eq!(policy.for_call(&build_request(1, "eth_getLogs", vec![])), 1_000_000);
+        assert_eq!(policy.for_call(&build_request(1, "debug_traceBlock", vec![])), DEFAULT_BYTES);
+
+        let block = build_request(1, "eth_getBlockByNumber", vec!["latest".into(), false.into()]);
+        let full_block = build_request(1, "eth_getBlockByNumber", vec!["latest".into(), true.into()]);
+        assert_eq!(policy.for_call(&block), 100_000);
+        assert_eq!(policy.for_call(&full_block), CAP_BYTES);
+    }
+
+    #[test]
+    fn should_sum_batch_limits_up_to_cap() {
+        let policy = ResponseSizePolicy::default();
+        let calls = vec![
+            build_request(1, "eth_blockNumber", vec![]),
+            build_request(2, "eth_getBalance", vec![]),
+        ];
+        assert_eq!(policy.for_request(&rpc::Request::Batch(calls)), 2 * SCALAR_BYTES);
+        let logs = (0..3).map(|id| build_request(id, "eth_getLogs", vec![])).collect();
+        assert_eq!(policy.for_request(&rpc::Request::Batch(logs)), CAP_BYTES);
+    }
+
+    #[test]
+    fn should_grow_up_to_cap() {
+        let policy = ResponseSizePolicy::new(1_000, 3_000);
+        assert_eq!(policy.grow(1_000), Some(2_000));
+        assert_eq!(policy.grow(2_000), Some(3_000));
+        assert_eq!(policy.grow(3_000), None);
+    }
+
+    #[test]
+    fn should_detect_oversize_rejections() {
+        let oversize = "Http body exceeds size limit of 500000 bytes.".to_string();
+        assert!(is_oversize(&Error::Transport(TransportError::Rejected(
+            RejectionCode::SysFatal,
+            oversize
+        ))));
+        assert!(!is_oversize(&Error::Transport(TransportError::Rejected(
+            RejectionCode::SysTransient,
+            "Timeout expired".into()
+        ))));
+    }
+}
@@ -0,0 +1,374 @@
+//! Retrying transport
+
+use crate::{
+    error::{self, Error, TransportError},
+    rpc, timer, BatchTransport, RequestId, Transport,
+};
+use futures::future::{BoxFuture, FutureExt};
+use ic_cdk::api::call::RejectionCode;
+use std::time::Duration;
+
+/// Which failed calls are retried and how long to wait in between.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    /// Number of retries after the first attempt.
+    pub max_retries: u32,
+    /// Wait before the first retry, doubled on every further retry.
+    pub backoff: Duration,
+    /// Longest wait between two attempts.
+    pub max_backoff: Duration,
+    /// Outcall rejections to retry.
+    pub rejection_codes: Vec<RejectionCode>,
+    /// HTTP statuses to retry.
+    pub http_statuses: Vec<u16>,
+    /// JSON-RPC error codes to retry.
+    pub rpc_codes: Vec<i64>,
+}
+
+impl Default for RetryPolicy {
+    /// Retries transient rejections, rate limits and gateway errors up to 3 times.
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(30),
+            rejection_codes: vec![RejectionCode::SysTransient],
+            http_statuses: vec![429, 502, 503, 504],
+            rpc_codes: vec![-32005],
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Whether a call failing with `err` should be retried.
+    ///
+    /// Queries refused as too large are never retried, they would fail again the same way.
+    pub fn should_retry(&self, err: &Error) -> bool {
+        match err {
+            Error::Transport(TransportError::Rejected(code, _)) => self.rejection_codes.contains(code),
+            Error::Transport(TransportError::Code(status, _)) => self.http_statuses.contains(status),
+            Error::Transport(TransportError::RateLimited(_)) => self.http_statuses.contains(&429),
+            Error::Rpc(_) if err.is_too_large() => false,
+            Error::Rpc(err) => self.rpc_codes.contains(&err.code.code()),
+            _ => false,
+        }
+    }
+
+    /// Wait before the given retry, counting from 0.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry);
+        self.backoff.saturating_mul(factor).min(self.max_backoff)
+    }
+}
+
+/// Transport retrying failed calls of another transport according to a [RetryPolicy].
+///
+/// Waits between attempts with [timer::sleep], so it only works inside a canister. To retry batches
+/// wrap the inner transport, `Batch::new(Retry::new(ICHttp::new(..)?, policy))`, then failed
+/// calls of a batch are sent again without the ones which succeeded.
+#[derive(Debug, Clone)]
+pub struct Retry<T> {
+    transport: T,
+    policy: RetryPolicy,
+    sleep: fn(Duration) -> BoxFuture<'static, error::Result<()>>,
+}
+
+impl<T: Transport> Retry<T> {
+    /// Create new retrying transport.
+    pub fn new(transport: T, policy: RetryPolicy) -> Self {
+        Retry {
+            transport,
+            policy,
+            sleep: |duration| timer::sleep(duration).boxed(),
+        }
+    }
+
+    /// Set the wait between attempts, [timer::sleep] by default.
+    ///
+    /// When the wait fails, the call fails with the last error of the transport.
+    pub fn set_sleep(&mut self, sleep: fn(Duration) -> BoxFuture<'static, error::Result<()>>) {
+        self.sleep = sleep;
+    }
+
+    /// Borrows the retry policy.
+    pub fn policy(&self) -> &RetryPolicy {
+        &self.policy
+    }
+
+    /// Borrows the inner transport.
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+}
+
+impl<T> Transport for Retry<T>
+where
+    T: Transport + Send + 'static,
+    T::Out: 'static + Send,
+{
+    type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+    fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+        self.transport.prepare(method, params)
+    }
+
+    fn send(&self, id: RequestId, request: rpc::Call) -> Self::Out {
+        let transport = self.transport.clone();
+        let policy = self.policy.clone();
+        let sleep = self.sleep;
+        async move {
+            let mut retry = 0;
+            loop {
+                match transport.send(id, request.clone()).await {
+                    Err(err) if retry < policy.max_retries && policy.should_retry(&err) => {
+                        if sleep(policy.delay(retry)).await.is_err() {
+                            return Err(err);
+                        }
+                        retry += 1;
+                    }
+                    res => return res,
+                }
+            }
+        }
+        .boxed()
+    }
+
+    fn set_max_response_bytes(&mut self, v: u64) {
+        self.transport.set_max_response_bytes(v);
+    }
+}
+
+impl<T> BatchTransport for Retry<T>
+where
+    T: BatchTransport + Send + 'static,
+    T::Out: 'static + Send,
+    T::Batch: 'static + Send,
+{
+    type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+    fn send_batch<I>(&self, requests: I) -> Self::Batch
+    where
+        I: IntoIterator<Item = (RequestId, rpc::Call)>,
+    {
+        let transport = self.transport.clone();
+        let policy = self.policy.clone();
+        let sleep = self.sleep;
+        let requests = requests.into_iter().collect::<Vec<_>>();
+        async move {
+            let mut results: Vec<Option<error::Result<rpc::Value>>> = vec![None; requests.len()];
+            let mut pending = (0..requests.len()).collect::<Vec<_>>();
+            let mut retry = 0;
+            loop {
+                let batch = pending.iter().map(|idx| requests[*idx].clone()).collect::<Vec<_>>();
+                let mut batch_error = None;
+                match transport.send_batch(batch).await {
+                    Err(err) if retry < policy.max_retries && policy.should_retry(&err) => batch_error = Some(err),
+                    Err(err) => return Err(err),
+                    Ok(answers) => {
+                        if answers.len() != pending.len() {
+                            return Err(Error::InvalidResponse("unexpected number of responses".into()));
+                        }
+                        let mut failed = vec![];
+                        for (idx, answer) in pending.into_iter().zip(answers) {
+                            let retried = matches!(answer, Err(ref err) if policy.should_retry(err));
+                            if retried && retry < policy.max_retries {
+                                failed.push(idx);
+                            }
+                            results[idx] = Some(answer);
+                        }
+                        pending = failed;
+                    }
+                }
+                // calls which are still pending keep their last error when the wait fails
+                if pending.is_empty() || sleep(policy.delay(retry)).await.is_err() {
+                    return match batch_error {
+                        Some(err) => Err(err),
+                        None => Ok(results
+                            .into_iter()
+                            .map(|res| res.expect("every request is answered"))
+                            .collect()),
+                    };
+                }
+                retry += 1;
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::{executor::block_on, future};
+    use parking_lot::Mutex;
+    use std::sync::Arc;
+
+    #[test]
+    fn should_retry_configured_failures() {
+        let policy = RetryPolicy::default();
+        let transient = Error::Transport(TransportError::Rejected(RejectionCode::SysTransient, "Timeout".into()));
+        let fatal = Error::Transport(TransportError::Rejected(RejectionCode::SysFatal, "size limit".into()));
+        assert!(policy.should_retry(&transient));
+        assert!(!policy.should_retry(&fatal));
+        assert!(policy.should_retry(&Error::Transport(TransportError::Code(503, "".into()))));
+        assert!(!policy.should_retry(&Error::Transport(TransportError::Code(400, "".into()))));
+        assert!(policy.should_retry(&Error::Transport(TransportError::RateLimited("".into()))));
+        assert!(policy.should_retry(&Error::Rpc(rpc::Error::new(rpc::ErrorCode::ServerError(-32005)))));
+        assert!(!policy.should_retry(&Error::Rpc(rpc::Error::invalid_params("bad"))));
+    }
+
+    #[test]
+    fn should_not_retry_too_large_queries() {
+        let mut too_large = rpc::Error::new(rpc::ErrorCode::ServerError(-32005));
+        too_large.message = "query returned more than 10000 results".into();
+        assert!(!RetryPolicy::default().should_retry(&Error::Rpc(too_large)));
+    }
+
+    #[test]
+    fn should_back_off_exponentially() {
+        let policy = RetryPolicy {
+            backoff: Duration::from_secs(2),
+            max_backoff: Duration::from_secs(10),
+            ..Default::default()
+        };
+        assert_eq!(policy.delay(0), Duration::from_secs(2));
+        assert_eq!(policy.delay(1), Duration::from_secs(4));
+        assert_eq!(policy.delay(2), Duration::from_secs(8));
+        assert_eq!(policy.delay(3), Duration::from_secs(10));
+        assert_eq!(policy.delay(40), Duration::from_secs(10));
+    }
+
+    /// Fails the first `failures` calls with HTTP 503, counting calls of all batch entries together,
+    /// except those with id 0 which always succeed. Records every call.
+    #[derive(Debug, Clone)]
+    struct Flaky {
+        failures: Arc<Mutex<u32>>,
+        calls: Arc<Mutex<Vec<RequestId>>>,
+    }
+
+    impl Flaky {
+        fn new(failures: u32) -> Self {
+            Flaky {
+                failures: Arc::new(Mutex::new(failures)),
+                calls: Default::default(),
+            }
+        }
+
+        fn answer(&self, id: RequestId) -> error::Result<rpc::Value> {
+            self.calls.lock().push(id);
+            let mut failures = self.failures.lock();
+            if *failures > 0 && id != 0 {
+                *failures -= 1;
+                return Err(Error::Transport(TransportError::Code(503, "".into())));
+            }
+            Ok(rpc::Value::from(id))
+        }
+    }
+
+    impl Transport for Flaky {
+        type Out = BoxFuture<'static, error::Result<rpc::Value>>;
+
+        fn prepare(&self, method: &str, params: Vec<rpc::Value>) -> (RequestId, rpc::Call) {
+            (1, crate::helpers::build_request(1, method, params))
+        }
+
+        fn send(&self, id: RequestId, _request: rpc::Call) -> Self::Out {
+            future::ready(self.answer(id)).boxed()
+        }
+    }
+
+    impl BatchTransport for Flaky {
+        type Batch = BoxFuture<'static, error::Result<Vec<error::Result<rpc::Value>>>>;
+
+        fn send_batch<I>(&self, requests: I) -> Self::Batch
+        where
+            I: IntoIterator<Item = (RequestId, rpc::Call)>,
+        {
+            let answers = requests.into_iter().map(|(id, _)| self.answer(id)).collect();
+            future::ready(Ok(answers)).boxed()
+        }
+    }
+
+    thread_local! {
+        static SLEPT: std::cell::RefCell<Vec<Duration>> = Default::default();
+    }
+
+    fn record_sleep(duration: Duration) -> BoxFuture<'static, error::Result<()>> {
+        SLEPT.with(|slept| slept.borrow_mut().push(duration));
+        future::ready(Ok(())).boxed()
+    }
+
+    fn retry(inner: Flaky) -> Retry<Flaky> {
+        let mut transport = Retry::new(inner, RetryPolicy::default());
+        transport.set_sleep(record_sleep);
+        SLEPT.with(|slept| slept.borrow_mut().clear());
+        transport
+    }
+
+    #[test]
+    fn should_retry_failed_calls() {
+        let inner = Flaky::new(2);
+        let transport = retry(inner.clone());
+
+        let (_, call) = transport.prepare("eth_blockNumber", vec![]);
+        assert_eq!(block_on(transport.send(1, call)), Ok(rpc::Value::from(1)));
+        assert_eq!(*inner.calls.lock(), vec![1, 1, 1]);
+        SLEPT.with(|slept| assert_eq!(*slept.borrow(), vec![Duration::from_secs(1), Duration::from_secs(2)]));
+
+        // gives up after max_retries
+        let inner = Flaky::new(10);
+        let transport = retry(inner.clone());
+        let (_, call) = transport.prepare("eth_blockNumber", vec![]);
+        assert!(block_on(transport.send(1, call)).is_err());
+        assert_eq!(inner.calls.lock().len(), 4);
+    }
+
+    fn failed_sleep(_duration: Duration) -> BoxFuture<'static, error::Result<()>> {
+        future::ready(Err(Error::Transport(TransportError::Rejected(
+            RejectionCode::CanisterReject,
+            "query".into(),
+        ))))
+        .boxed()
+    }
+
+    #[test]
+    fn should_give_up_when_the_wait_fails() {
+        let inner = Flaky::new(1);
+        let mut transport = retry(inner.clone());
+        transport.set_sleep(failed_sleep);
+
+        let (_, call) = transport.prepare("eth_blockNumber", vec![]);
+        assert_eq!(
+            block_on(transport.send(1, call)),
+            Err(Error::Transport(TransportError::Code(503, "".into())))
+        );
+        assert_eq!(*inner.calls.lock(), vec![1]);
+
+        let inner = Flaky::new(1);
+        let mut transport = retry(inner.clone());
+        transport.set_sleep(failed_sleep);
+        let requests = (0..2).map(|id| (id, transport.prepare("eth_blockNumber", vec![]).1));
+        let answers = block_on(transport.send_batch(requests)).unwrap();
+        assert_eq!(
+            answers,
+            vec![
+                Ok(rpc::Value::from(0)),
+                Err(Error::Transport(TransportError::Code(503, "".into())))
+            ]
+        );
+        assert_eq!(*inner.calls.lock(), vec![0, 1]);
+    }
+
+    #[test]
+    fn should_resend_only_failed_batch_calls() {
+        let inner = Flaky::new(1);
+        let transport = retry(inner.clone());
+
+        let requests = (0..3).map(|id| (id, transport.prepare("eth_blockNumber", vec![]).1));
+        let answers = block_on(transport.send_batch(requests)).unwrap();
+        assert_eq!(answers, (0..3).map(|id| Ok(rpc::Value::from(id))).collect::<Vec<_>>());
+        // request 0 never fails, 1 fails once and 2 succeeds on the first attempt
+        assert_eq!(*inner.calls.lock(), vec![0, 1, 2, 1]);
+        SLEPT.with(|slept| assert_eq!(slept.borrow().len(), 1));
+    }
+}
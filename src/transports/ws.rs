@@ -175,10 +175,10 @@ impl WsServerTask {
         let (sender, receiver) = match handshake.await? {
             ServerResponse::Accepted { .. } => client.into_builder().finish(),
             ServerResponse::Redirect { status_code, .. } => {
-                return Err(error::Error::Transport(TransportError::Code(status_code)))
+                return Err(error::Error::Transport(TransportError::Code(status_code, String::new())))
             }
             ServerResponse::Rejected { status_code } => {
-                return Err(error::Error::Transport(TransportError::Code(status_code)))
+                return Err(error::Error::Transport(TransportError::Code(status_code, String::new())))
             }
         };
 
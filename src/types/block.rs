@@ -134,6 +134,10 @@ pub enum BlockNumber {
     Earliest,
     /// Pending block (not yet part of the blockchain)
     Pending,
+    /// Latest block considered final by the consensus layer
+    Finalized,
+    /// Latest block considered safe from reorgs by the consensus layer
+    Safe,
     /// Block by number from canon chain
     Number(U64),
 }
@@ -154,6 +158,8 @@ impl Serialize for BlockNumber {
             BlockNumber::Latest => serializer.serialize_str("latest"),
             BlockNumber::Earliest => serializer.serialize_str("earliest"),
             BlockNumber::Pending => serializer.serialize_str("pending"),
+            BlockNumber::Finalized => serializer.serialize_str("finalized"),
+            BlockNumber::Safe => serializer.serialize_str("safe"),
         }
     }
 }
@@ -168,6 +174,8 @@ impl<'a> Deserialize<'a> for BlockNumber {
             "latest" => Ok(BlockNumber::Latest),
             "earliest" => Ok(BlockNumber::Earliest),
             "pending" => Ok(BlockNumber::Pending),
+            "finalized" => Ok(BlockNumber::Finalized),
+            "safe" => Ok(BlockNumber::Safe),
             _ if value.starts_with("0x") => U64::from_str_radix(&value[2..], 16)
                 .map(BlockNumber::Number)
                 .map_err(|e| D::Error::custom(format!("invalid block number: {}", e))),
@@ -31,20 +31,16 @@ pub struct SignedData {
 /// They happen to be the same much of the time but it is recommended to set
 /// this for signing transactions.
 ///
-/// `TransactionParameters` implements `Default` and uses `100_000` as the
-/// default `gas` to use for the transaction. This is more than enough for
-/// simple transactions sending ETH between accounts but may not be enough when
-/// interacting with complex contracts. It is recommended when interacting
-/// with contracts to use `Eth::estimate_gas` to estimate the required gas for
-/// the transaction.
+/// When `gas` is omitted it is estimated with `eth_estimateGas`, plus a safety
+/// margin as the state may change before the transaction is included.
 #[derive(Clone, Debug, PartialEq)]
 pub struct TransactionParameters {
     /// Transaction nonce (None for account transaction count)
     pub nonce: Option<U256>,
     /// To address
     pub to: Option<Address>,
-    /// Supplied gas
-    pub gas: U256,
+    /// Supplied gas (None for estimated gas)
+    pub gas: Option<U256>,
     /// Gas price (None for estimated gas price)
     pub gas_price: Option<U256>,
     /// Transferred value
@@ -63,20 +59,12 @@ pub struct TransactionParameters {
     pub max_priority_fee_per_gas: Option<U256>,
 }
 
-/// The default fas for transactions.
-///
-/// Unfortunately there is no way to construct `U256`s with const functions for
-/// constants so we just build it from it's `u64` words. Note that there is a
-/// unit test to verify that it is constructed correctly and holds the expected
-/// value of 100_000.
-const TRANSACTION_DEFAULT_GAS: U256 = U256([100_000, 0, 0, 0]);
-
 impl Default for TransactionParameters {
     fn default() -> Self {
         TransactionParameters {
             nonce: None,
             to: None,
-            gas: TRANSACTION_DEFAULT_GAS,
+            gas: None,
             gas_price: None,
             value: U256::zero(),
             data: Bytes::default(),
@@ -94,7 +82,7 @@ impl From<CallRequest> for TransactionParameters {
         TransactionParameters {
             nonce: None,
             to: call.to,
-            gas: call.gas.unwrap_or(TRANSACTION_DEFAULT_GAS),
+            gas: call.gas,
             gas_price: call.gas_price,
             value: call.value.unwrap_or_default(),
             data: call.data.unwrap_or_default(),
@@ -112,7 +100,7 @@ impl From<TransactionParameters> for CallRequest {
         CallRequest {
             from: None,
             to: val.to,
-            gas: Some(val.gas),
+            gas: val.gas,
             gas_price: val.gas_price,
             value: Some(val.value),
             data: Some(val.data),
@@ -140,13 +128,3 @@ pub struct SignedTransaction {
     /// The transaction hash for the RLP encoded transaction.
     pub transaction_hash: H256,
 }
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-
-    #[test]
-    fn verify_transaction_default_gas() {
-        assert_eq!(TRANSACTION_DEFAULT_GAS, U256::from(100_000));
-    }
-}